@@ -0,0 +1,4 @@
+-- Add down migration script here
+drop index if exists refresh_tokens_family_index;
+drop index if exists refresh_tokens_token_hash_index;
+drop table if exists refresh_tokens;
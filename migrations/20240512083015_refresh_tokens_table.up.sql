@@ -0,0 +1,15 @@
+-- Add up migration script here
+create table if not exists refresh_tokens (
+    _id uuid not null primary key default gen_random_uuid(),
+    create_at timestamptz not null default now(),
+    expire_at timestamptz not null,
+    -- all tokens rotated from the same login share one family
+    family uuid not null default gen_random_uuid(),
+    replaced_by uuid references refresh_tokens(_id),
+    revoke_at timestamptz,
+    token_hash text not null,
+    user_id uuid not null references users(_id)
+);
+
+create unique index if not exists refresh_tokens_token_hash_index on refresh_tokens(token_hash);
+create index if not exists refresh_tokens_family_index on refresh_tokens(family);
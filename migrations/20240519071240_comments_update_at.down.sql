@@ -0,0 +1,3 @@
+-- Add down migration script here
+drop trigger if exists comments_update_at_trigger on comments;
+alter table comments drop column if exists update_at;
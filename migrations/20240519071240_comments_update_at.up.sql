@@ -0,0 +1,8 @@
+-- Add up migration script here
+alter table comments add column if not exists update_at timestamptz not null default now();
+
+update comments set update_at = create_at;
+
+create trigger comments_update_at_trigger
+before update on comments
+for each row execute procedure update_at_column();
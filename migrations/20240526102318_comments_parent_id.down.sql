@@ -0,0 +1,3 @@
+-- Add down migration script here
+drop index if exists comments_parent_id_index;
+alter table comments drop column if exists parent_id;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+alter table comments add column if not exists parent_id uuid references comments(_id) on delete cascade;
+
+create index if not exists comments_parent_id_index on comments(parent_id);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+drop index if exists follows_following_id_index;
+drop table if exists follows;
@@ -0,0 +1,10 @@
+-- Add up migration script here
+create table if not exists follows (
+    create_at timestamptz not null default now(),
+    follower_id uuid not null references users(_id),
+    following_id uuid not null references users(_id),
+    primary key (follower_id, following_id),
+    check (follower_id <> following_id)
+);
+
+create index if not exists follows_following_id_index on follows(following_id);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+drop index if exists topic_slugs_topic_index;
+drop table if exists topic_slugs;
+drop index if exists topics_slug_index;
+alter table topics drop column if exists slug;
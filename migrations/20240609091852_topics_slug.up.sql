@@ -0,0 +1,25 @@
+-- Add up migration script here
+alter table topics add column if not exists slug text;
+
+-- existing topics get a slug from their title, suffixed with part of the id to stay unique,
+-- the trigger is disabled so the backfill keeps update_at (which is unique) as it was
+alter table topics disable trigger topics_update_at_trigger;
+
+update topics
+set slug = coalesce(nullif(trim(both '-' from lower(regexp_replace(title, '[^[:alnum:]]+', '-', 'g'))), ''), 'topic') || '-' || left(_id::text, 8)
+where slug is null;
+
+alter table topics enable trigger topics_update_at_trigger;
+
+alter table topics alter column slug set not null;
+
+create unique index if not exists topics_slug_index on topics(slug);
+
+-- previous slugs of a topic, so links shared before a title change keep working
+create table if not exists topic_slugs (
+    slug text not null primary key,
+    create_at timestamptz not null default now(),
+    topic uuid not null references topics(_id) on delete cascade
+);
+
+create index if not exists topic_slugs_topic_index on topic_slugs(topic);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+drop index if exists comments_search_index;
+alter table comments drop column if exists search;
+drop index if exists topics_search_index;
+alter table topics drop column if exists search;
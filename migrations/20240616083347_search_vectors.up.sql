@@ -0,0 +1,13 @@
+-- Add up migration script here
+alter table topics add column if not exists search tsvector
+generated always as (
+    setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(content, '')), 'B')
+) stored;
+
+create index if not exists topics_search_index on topics using gin(search);
+
+alter table comments add column if not exists search tsvector
+generated always as (to_tsvector('english', coalesce(content, ''))) stored;
+
+create index if not exists comments_search_index on comments using gin(search);
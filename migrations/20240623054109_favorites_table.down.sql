@@ -0,0 +1,16 @@
+-- Add down migration script here
+alter table topics add column if not exists favorite int not null default 0;
+alter table users add column if not exists favorite uuid[] not null default array[]::uuid[];
+
+alter table topics disable trigger topics_update_at_trigger;
+update topics t
+set favorite = (select count(*) from favorites f where f.topic_id = t._id);
+alter table topics enable trigger topics_update_at_trigger;
+
+alter table users disable trigger users_update_at_trigger;
+update users u
+set favorite = array(select f.topic_id from favorites f where f.user_id = u._id order by f.create_at);
+alter table users enable trigger users_update_at_trigger;
+
+drop index if exists favorites_topic_id_index;
+drop table if exists favorites;
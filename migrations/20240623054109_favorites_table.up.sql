@@ -0,0 +1,21 @@
+-- Add up migration script here
+create table if not exists favorites (
+    user_id uuid not null references users(_id) on delete cascade,
+    topic_id uuid not null references topics(_id) on delete cascade,
+    create_at timestamptz not null default now(),
+    primary key (user_id, topic_id)
+);
+
+create index if not exists favorites_topic_id_index on favorites(topic_id);
+
+-- favorites only lived in the users' arrays, ids of deleted topics are dropped on the way
+insert into favorites (user_id, topic_id)
+select u._id, f.topic_id
+from users u
+cross join lateral unnest(u.favorite) as f(topic_id)
+join topics t on t._id = f.topic_id
+on conflict do nothing;
+
+alter table users drop column if exists favorite;
+
+alter table topics drop column if exists favorite;
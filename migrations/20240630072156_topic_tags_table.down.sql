@@ -0,0 +1,67 @@
+-- Add down migration script here
+alter table topics add column if not exists tags text[] not null default array[]::text[];
+alter table tags add column if not exists topics uuid[] not null default array[]::uuid[];
+
+alter table topics disable trigger topics_update_at_trigger;
+update topics t
+set tags = array(
+    select g.tag
+    from topic_tags tt
+    join tags g on g._id = tt.tag_id
+    where tt.topic_id = t._id
+    order by g.tag
+);
+alter table topics enable trigger topics_update_at_trigger;
+
+update tags g
+set topics = array(
+    select tt.topic_id
+    from topic_tags tt
+    where tt.tag_id = g._id
+    order by tt.create_at
+);
+
+create or replace function update_tags(tags_arr text[], topic_id uuid) returns void
+as $$
+declare
+    tag_txt text;
+begin
+    foreach tag_txt in array tags_arr
+    loop
+        update tags t
+        set topics =
+            case
+                when topic_id = any(t.topics::uuid[]) then
+                    topics
+                else
+                    array_append(t.topics, topic_id)
+            end
+        where t.tag = tag_txt;
+
+        if not found then
+            insert into tags (tag, topics)
+            values (tag_txt, array[topic_id]);
+        end if;
+    end loop;
+end;
+$$ language plpgsql;
+
+create or replace function remove_tags(tags_removed text[], topic_id uuid) returns void
+as $$
+declare
+    tag_txt text;
+begin
+    foreach tag_txt in array tags_removed
+    loop
+        update tags t
+        set topics = array_remove(t.topics, topic_id)
+        where t.tag = tag_txt;
+    end loop;
+end;
+$$ language plpgsql;
+
+drop index if exists tags_tag_index;
+create unique index if not exists tags_tag_create_at_index on tags(tag, create_at desc);
+
+drop index if exists topic_tags_tag_id_index;
+drop table if exists topic_tags;
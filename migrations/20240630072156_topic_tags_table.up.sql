@@ -0,0 +1,39 @@
+-- Add up migration script here
+create table if not exists topic_tags (
+    topic_id uuid not null references topics(_id) on delete cascade,
+    tag_id uuid not null references tags(_id) on delete cascade,
+    create_at timestamptz not null default now(),
+    primary key (topic_id, tag_id)
+);
+
+create index if not exists topic_tags_tag_id_index on topic_tags(tag_id);
+
+-- the same name could end up in several rows before, the oldest one is kept
+delete from tags t
+using tags d
+where t.tag = d.tag and (t.create_at, t._id) > (d.create_at, d._id);
+
+drop index if exists tags_tag_create_at_index;
+create unique index if not exists tags_tag_index on tags(tag);
+
+-- both arrays described the same relation, topics.tags is taken as the source of truth
+insert into tags (tag)
+select distinct unnest(tags)
+from topics
+on conflict (tag) do nothing;
+
+insert into topic_tags (topic_id, tag_id)
+select t._id, g._id
+from topics t
+cross join lateral unnest(t.tags) as n(tag)
+join tags g on g.tag = n.tag
+on conflict do nothing;
+
+delete from tags g
+where not exists (select 1 from topic_tags tt where tt.tag_id = g._id);
+
+drop function if exists remove_tags(text[], uuid);
+drop function if exists update_tags(text[], uuid);
+
+alter table tags drop column if exists topics;
+alter table topics drop column if exists tags;
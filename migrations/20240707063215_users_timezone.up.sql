@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- IANA zone name (e.g. 'Asia/Shanghai') used to render local time strings, null means UTC.
+alter table users add column if not exists timezone text;
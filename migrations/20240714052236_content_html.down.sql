@@ -0,0 +1,3 @@
+-- Add down migration script here
+alter table comments drop column if exists content_html;
+alter table topics drop column if exists content_html;
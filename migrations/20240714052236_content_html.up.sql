@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Markdown rendered and sanitized by the app on write, `migrate up` renders the existing rows.
+alter table topics add column if not exists content_html text;
+alter table comments add column if not exists content_html text;
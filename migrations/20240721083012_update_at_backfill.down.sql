@@ -0,0 +1,7 @@
+-- Add down migration script here
+create or replace function update_at_column() returns trigger as $$
+begin
+    new.update_at = now();
+    return new;
+end;
+$$ language plpgsql;
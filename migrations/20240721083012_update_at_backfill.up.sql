@@ -0,0 +1,12 @@
+-- Add up migration script here
+-- `migrate backfill` sets `conduit.backfill` for its own transactions, rows it fills derived
+-- columns of keep their update_at without pausing the triggers.
+create or replace function update_at_column() returns trigger as $$
+begin
+    if current_setting('conduit.backfill', true) = 'on' then
+        return new;
+    end if;
+    new.update_at = now();
+    return new;
+end;
+$$ language plpgsql;
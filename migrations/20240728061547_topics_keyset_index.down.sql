@@ -0,0 +1,3 @@
+-- Add down migration script here
+drop index if exists topics_create_at_id_index;
+drop index if exists topics_update_at_id_index;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- keyset pagination walks topics by (update_at, _id) or (create_at, _id)
+create index if not exists topics_update_at_id_index on topics(update_at desc, _id desc);
+create index if not exists topics_create_at_id_index on topics(create_at desc, _id desc);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+drop index if exists users_username_index;
+drop index if exists users_email_index;
@@ -0,0 +1,19 @@
+-- Add up migration script here
+-- Accounts sharing an email or username with an older account get a suffixed copy of it,
+-- the older account keeps the original.
+update users u
+set username = u.username || '-' || left(u._id::text, 8)
+where exists (
+    select 1 from users o
+    where o.username = u.username and (o.create_at, o._id) < (u.create_at, u._id)
+);
+
+update users u
+set email = left(u._id::text, 8) || '+' || u.email
+where exists (
+    select 1 from users o
+    where o.email = u.email and (o.create_at, o._id) < (u.create_at, u._id)
+);
+
+create unique index if not exists users_email_index on users(email);
+create unique index if not exists users_username_index on users(username);
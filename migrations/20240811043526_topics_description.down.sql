@@ -0,0 +1,2 @@
+-- Add down migration script here
+alter table topics drop column if exists description;
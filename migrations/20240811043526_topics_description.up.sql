@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- Summary given by v2 (RealWorld) clients, null on topics created without one.
+alter table topics add column if not exists description text;
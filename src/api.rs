@@ -1,55 +1,174 @@
+use std::{collections::BTreeMap, num::ParseIntError};
+
+use anyhow::anyhow;
 use axum::{
     http::StatusCode,
-    response::{IntoResponse, Response}, Json,
+    response::{IntoResponse, Response},
+    Json,
 };
 use serde_json::json;
+use tracing::{error, warn};
+use validator::ValidationErrors;
 
+pub mod comment;
 pub mod common;
+pub mod health;
+pub mod search;
 pub mod tag;
+pub mod token;
 pub mod topic;
 pub mod user;
 pub mod utils;
+pub mod v2;
 
 use self::utils::jwt::AuthError;
 
-pub static PAGE_SIZE: i32 = 10;
+// Field name to the messages of every check that field failed.
+pub type FieldErrors = BTreeMap<String, Vec<String>>;
 
 pub enum AppError {
     Auth(AuthError),
-    Duplicate(anyhow::Error),
+    BadRequest(anyhow::Error),
+    Conflict(anyhow::Error),
+    Forbidden(anyhow::Error),
     Internal(anyhow::Error),
+    NotFound(anyhow::Error),
+    Validation(FieldErrors),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
+impl AppError {
+    pub fn field(field: &str, msg: &str) -> Self {
+        Self::Validation(FieldErrors::from([(
+            field.to_string(),
+            vec![msg.to_string()],
+        )]))
+    }
+
+    pub fn not_found(msg: &str) -> Self {
+        Self::NotFound(anyhow!(msg.to_string()))
+    }
+
+    pub fn status_and_msg(&self) -> (StatusCode, String) {
         match self {
-            Self::Auth(err) => err.into_response(),
-            Self::Duplicate(err) => (
-                StatusCode::CONFLICT,
-                Json(json!({
-                    "code": StatusCode::CONFLICT.as_u16(),
-                    "msg": format!("Duplicate entry: {}.", err),
-                })),
-            )
-                .into_response(),
-            Self::Internal(err) => (
+            Self::Auth(err) => err.status_and_msg(),
+            Self::BadRequest(err) => (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid request: {}.", err),
+            ),
+            Self::Conflict(err) => (StatusCode::CONFLICT, format!("Duplicate entry: {}.", err)),
+            Self::Forbidden(err) => (
+                StatusCode::FORBIDDEN,
+                format!("Permission denied: {}.", err),
+            ),
+            // The details may leak queries or internals, they only go to the logs.
+            Self::Internal(_) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "code": StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-                    "msg": format!("Something went wrong: {}.", err),
-                })),
-            )
-                .into_response(),
+                "Something went wrong.".to_string(),
+            ),
+            Self::NotFound(err) => (StatusCode::NOT_FOUND, format!("{}.", err)),
+            Self::Validation(_) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Validation failed.".to_string(),
+            ),
+        }
+    }
+
+    pub fn log(&self) {
+        if let Self::Internal(err) = self {
+            error!("Internal error: {:#}", err);
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        self.log();
+        let (code, msg) = self.status_and_msg();
+        let mut body = json!({ "code": code.as_u16(), "msg": msg });
+        if let Self::Validation(errors) = self {
+            body["errors"] = json!(errors);
+        }
+        (code, Json(body)).into_response()
+    }
+}
+
+// Outside of handlers (the migrate subcommand) errors are only logged.
+impl From<AppError> for anyhow::Error {
+    fn from(err: AppError) -> Self {
+        match err {
+            AppError::Auth(err) => anyhow!(err.status_and_msg().1),
+            AppError::BadRequest(err)
+            | AppError::Conflict(err)
+            | AppError::Forbidden(err)
+            | AppError::Internal(err)
+            | AppError::NotFound(err) => err,
+            AppError::Validation(errors) => anyhow!("Validation failed: {:?}", errors),
         }
     }
 }
 
-impl<E> From<E> for AppError
-where
-    E: Into<anyhow::Error>,
-{
-    fn from(err: E) -> Self {
-        Self::Internal(err.into())
+impl From<anyhow::Error> for AppError {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<sqlx::Error>() {
+            Ok(err) => err.into(),
+            Err(err) => Self::Internal(err),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::RowNotFound => Self::not_found("Resource not found"),
+            // Constraint names and the offending values stay in the logs.
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                warn!("Unique violation: {}", db.message());
+                match db.constraint() {
+                    Some("topics_slug_index" | "topic_slugs_pkey") => {
+                        Self::field("title", "is already used by another topic, try again")
+                    }
+                    Some("users_email_index") => Self::field("email", "is already taken"),
+                    Some("users_username_index") => Self::field("username", "is already taken"),
+                    Some("tags_tag_index") => {
+                        Self::field("tags", "were changed at the same time, try again")
+                    }
+                    _ => Self::Conflict(anyhow!("the resource already exists")),
+                }
+            }
+            sqlx::Error::Database(db) if db.is_foreign_key_violation() => {
+                Self::not_found("Referenced resource not found")
+            }
+            _ => Self::Internal(err.into()),
+        }
+    }
+}
+
+impl From<ParseIntError> for AppError {
+    fn from(err: ParseIntError) -> Self {
+        Self::BadRequest(anyhow!("invalid number, {}", err))
+    }
+}
+
+impl From<chrono::ParseError> for AppError {
+    fn from(err: chrono::ParseError) -> Self {
+        Self::BadRequest(anyhow!("invalid date, {}", err))
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        Self::Validation(
+            errors
+                .field_errors()
+                .into_iter()
+                .map(|(field, errors)| {
+                    (
+                        field.to_string(),
+                        errors.iter().map(|err| err.to_string()).collect(),
+                    )
+                })
+                .collect(),
+        )
     }
 }
 
@@ -0,0 +1,79 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde_json::{json, Map, Value};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{
+    common,
+    utils::{jwt::Claims, markdown},
+    AppError,
+};
+use crate::db::CommentPayload;
+
+pub async fn update_comment(
+    claims: Claims,
+    State(pool): State<Pool<Postgres>>,
+    Path(comment_id): Path<Uuid>,
+    Json(payload): Json<CommentPayload>,
+) -> Result<Json<Value>, AppError> {
+    println!("\n{:?}\n", claims);
+
+    payload.validate()?;
+
+    let mut tx = pool.begin().await?;
+
+    common::ensure_comment_permission(&mut *tx, comment_id, claims.cuid).await?;
+
+    sqlx::query(
+        r#"
+            update comments
+            set content = $1, content_html = $2
+            where _id = $3
+        "#,
+    )
+    .bind(&payload.content)
+    .bind(markdown::render(&payload.content))
+    .bind(comment_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let comment = common::query_comment(&pool, comment_id).await?;
+
+    let mut res = Map::new();
+    res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
+    res.insert("msg".to_string(), json!("Comment update succeed."));
+    res.insert("comment".to_string(), json!(&comment));
+
+    Ok(Json(json!(res)))
+}
+
+pub async fn delete_comment(
+    claims: Claims,
+    State(pool): State<Pool<Postgres>>,
+    Path(comment_id): Path<Uuid>,
+) -> Result<Json<Value>, AppError> {
+    println!("\n{:?}\n", claims);
+
+    let mut tx = pool.begin().await?;
+
+    let topic_id = common::ensure_comment_permission(&mut *tx, comment_id, claims.cuid).await?;
+
+    let deleted = common::delete_comment(&mut tx, comment_id, topic_id).await?;
+
+    tx.commit().await?;
+
+    let mut res = Map::new();
+    res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
+    res.insert("msg".to_string(), json!("Comment delete succeed."));
+    res.insert("comment_id".to_string(), json!(&comment_id));
+    res.insert("deleted".to_string(), json!(&deleted));
+
+    Ok(Json(json!(res)))
+}
@@ -1,37 +1,194 @@
-use sqlx::{Pool, Postgres};
+use anyhow::anyhow;
+use chrono_tz::Tz;
+use sqlx::{PgConnection, PgExecutor, Pool, Postgres};
 use uuid::Uuid;
+use validator::Validate;
 
-use super::{utils::topic_fmt, AppError, PAGE_SIZE};
-use crate::db::{Tag, Topic, User};
+use super::{
+    tag,
+    utils::{
+        comment_tree,
+        jwt::AuthError,
+        markdown,
+        pagination::Pagination,
+        password,
+        topic_filter::{TopicFilter, TopicSort},
+        topic_fmt, topic_slug,
+    },
+    AppError,
+};
+use crate::db::{Comment, NewComment, NewTopic, NewUser, Profile, Tag, Topic, User};
 
-pub async fn query_user(pool: &Pool<Postgres>, user_id: Uuid) -> Result<User, AppError> {
+// Writes take a `&mut PgConnection` so the handler decides which transaction they run in,
+// single statement reads take any executor (the pool or an open transaction).
+
+pub async fn query_user(executor: impl PgExecutor<'_>, user_id: Uuid) -> Result<User, AppError> {
     let user: User = sqlx::query_as(
         r#"
-            select _id, avatar, bio, birthday, create_at, email, favorite, gender, job, nickname, phone, update_at, username
+            select _id, avatar, bio, birthday, create_at, email, array(select topic_id from favorites where user_id = users._id order by create_at) as favorite, gender, job, nickname, phone, timezone, update_at, username
             from users
             where _id = $1
         "#
     )
-    .bind(&user_id)
-    .fetch_one(pool)
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await?
+    .ok_or_else(|| AppError::not_found("User not found"))?;
+
+    Ok(user)
+}
+
+// Public part of a user, `following` tells whether `viewer_id` follows them.
+pub async fn query_profile(
+    executor: impl PgExecutor<'_>,
+    username: &str,
+    viewer_id: Option<Uuid>,
+) -> Result<Profile, AppError> {
+    let profile: Profile = sqlx::query_as(
+        r#"
+            select _id, avatar, bio, exists(
+                select 1 from follows f
+                where f.follower_id = $2 and f.following_id = u._id
+            ) as following, nickname, username
+            from users u
+            where username = $1
+        "#,
+    )
+    .bind(username)
+    .bind(viewer_id)
+    .fetch_optional(executor)
+    .await?
+    .ok_or_else(|| AppError::not_found("User not found"))?;
+
+    Ok(profile)
+}
+
+// Looks the user up by email and checks the password, legacy hashes are upgraded on the way.
+// Verifying the password is slow on purpose, it runs without holding a connection.
+pub async fn authenticate(
+    pool: &Pool<Postgres>,
+    hash_salt: Option<&str>,
+    email: String,
+    password: String,
+) -> Result<User, AppError> {
+    let mut user: User = sqlx::query_as(
+        r#"
+            select _id, avatar, bio, birthday, create_at, email, array(select topic_id from favorites where user_id = users._id order by create_at) as favorite, gender, job, nickname, password, phone, timezone, update_at, username
+            from users
+            where email = $1
+        "#
+    )
+    .bind(&email)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(AppError::Auth(AuthError::InvalidCredentials))?;
+
+    let hashed_password = user.password.take().unwrap_or_default();
+    if !password::verify(password.clone(), hashed_password.clone()).await? {
+        return Err(AppError::Auth(AuthError::InvalidCredentials));
+    }
+
+    if password::needs_rehash(&hashed_password, hash_salt) {
+        let rehashed_password = password::hash(password).await?;
+        sqlx::query(
+            r#"
+                update users
+                set password = $1
+                where _id = $2
+            "#,
+        )
+        .bind(&rehashed_password)
+        .bind(user._id)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(user)
+}
+
+pub async fn create_user(conn: &mut PgConnection, new_user: NewUser) -> Result<User, AppError> {
+    new_user.validate()?;
+
+    let count: i64 = sqlx::query_scalar(
+        r#"
+            select count(*)
+            from users
+            where email = $1 or username = $2
+        "#,
+    )
+    .bind(&new_user.email)
+    .bind(&new_user.username)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if count > 0 {
+        return Err(AppError::Conflict(anyhow!(
+            "Email or username already exists"
+        )));
+    }
+
+    let hashed_password = password::hash(new_user.password).await?;
+    let user: User = sqlx::query_as(
+        r#"
+            insert into users (email, password, username)
+            values ($1, $2, $3)
+            returning _id, avatar, bio, birthday, create_at, email, array(select topic_id from favorites where user_id = users._id order by create_at) as favorite, gender, job, nickname, phone, timezone, update_at, username
+        "#,
+    )
+    .bind(&new_user.email)
+    .bind(&hashed_password)
+    .bind(&new_user.username)
+    .fetch_one(&mut *conn)
     .await?;
 
     Ok(user)
 }
 
-pub async fn query_topic(pool: &Pool<Postgres>, topic_id: Uuid) -> Result<Topic, AppError> {
+pub async fn follow_user(
+    executor: impl PgExecutor<'_>,
+    follower_id: Uuid,
+    username: &str,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+            insert into follows (follower_id, following_id)
+            select $1, _id from users where username = $2
+            on conflict do nothing
+        "#,
+    )
+    .bind(follower_id)
+    .bind(username)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn unfollow_user(
+    executor: impl PgExecutor<'_>,
+    follower_id: Uuid,
+    username: &str,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+            delete from follows
+            where follower_id = $1 and following_id = (select _id from users where username = $2)
+        "#,
+    )
+    .bind(follower_id)
+    .bind(username)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn query_topic(executor: impl PgExecutor<'_>, topic_id: Uuid) -> Result<Topic, AppError> {
     let topic: Topic = sqlx::query_as(
         r#"
-            select _id, comments, (
-                select json_agg(cs) from (
-                    select _id, content, create_at, topic, user_id
-                    from comments
-                    where topic = $1
-                    order by create_at desc
-                ) as cs
-            ) as comments_arr, content, create_at, favorite, tags, title, update_at, user_id, (
+            select _id, comments, content, content_html, create_at, (select count(*) from favorites where topic_id = t._id)::int as favorite, slug, array(select g.tag from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = t._id order by g.tag) as tags, title, update_at, user_id, (
                 select row_to_json(u) from (
-                    select _id, avatar, bio, birthday, to_char(create_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as create_at, email, favorite, gender, job, nickname, phone, to_char(update_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as update_at, username
+                    select _id, avatar, bio, birthday, to_char(create_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as create_at, email, array(select topic_id from favorites where user_id = users._id order by create_at) as favorite, gender, job, nickname, phone, to_char(update_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as update_at, username
                     from users
                     where _id = t.user_id
                 ) u
@@ -40,17 +197,167 @@ pub async fn query_topic(pool: &Pool<Postgres>, topic_id: Uuid) -> Result<Topic,
             where _id = $1
         "#,
     )
-    .bind(&topic_id)
+    .bind(topic_id)
+    .fetch_optional(executor)
+    .await?
+    .ok_or_else(|| AppError::not_found("Topic not found"))?;
+
+    Ok(topic)
+}
+
+pub async fn query_comment(
+    executor: impl PgExecutor<'_>,
+    comment_id: Uuid,
+) -> Result<Comment, AppError> {
+    let comment: Comment = sqlx::query_as(
+        r#"
+            select _id, content, content_html, create_at, parent_id, topic, update_at, user_id, (
+                select count(*) from comments r where r.parent_id = c._id
+            ) as reply_count, (
+                select row_to_json(u) from (
+                    select _id, avatar, bio, nickname, username
+                    from users
+                    where _id = c.user_id
+                ) u
+            ) as user
+            from comments c
+            where _id = $1
+        "#,
+    )
+    .bind(comment_id)
+    .fetch_optional(executor)
+    .await?
+    .ok_or_else(|| AppError::not_found("Comment not found"))?;
+
+    Ok(comment)
+}
+
+// Loads the top level comments after `cursor` (keyset on `create_at, _id`) together with
+// their replies up to `depth` levels, every comment carries its author's public profile.
+pub async fn query_comments(
+    pool: &Pool<Postgres>,
+    topic_id: Uuid,
+    pagination: &Pagination,
+    depth: i32,
+) -> Result<(Vec<Comment>, Option<String>, i64), AppError> {
+    let comments: Vec<Comment> = sqlx::query_as(
+        r#"
+            with recursive tree as (
+                select * from (
+                    select _id, content, content_html, create_at, parent_id, topic, update_at, user_id, 1 as depth
+                    from comments
+                    where topic = $1 and parent_id is null
+                        and ($2::timestamptz is null or (create_at, _id) < ($2, $3))
+                    order by create_at desc, _id desc
+                    limit $4
+                ) as top
+                union all
+                select c._id, c.content, c.content_html, c.create_at, c.parent_id, c.topic, c.update_at, c.user_id, tree.depth + 1
+                from comments c
+                join tree on c.parent_id = tree._id
+                where tree.depth < $5
+            )
+            select _id, content, content_html, create_at, parent_id, topic, update_at, user_id, (
+                select count(*) from comments r where r.parent_id = tree._id
+            ) as reply_count, (
+                select row_to_json(u) from (
+                    select _id, avatar, bio, nickname, username
+                    from users
+                    where _id = tree.user_id
+                ) u
+            ) as user
+            from tree
+            order by depth, create_at desc, _id desc
+        "#,
+    )
+    .bind(topic_id)
+    .bind(pagination.after_at())
+    .bind(pagination.after_id())
+    .bind(pagination.fetch_limit())
+    .bind(depth)
+    .fetch_all(pool)
+    .await?;
+
+    let total: i64 = sqlx::query_scalar(
+        r#"
+            select count(*)
+            from comments
+            where topic = $1 and parent_id is null
+        "#,
+    )
+    .bind(topic_id)
     .fetch_one(pool)
     .await?;
 
-    Ok(topic)
+    let (comments, next_cursor) = pagination.page(comment_tree::build(comments), |comment| {
+        (comment.create_at.timestamp_micros(), comment._id)
+    });
+
+    Ok((comments, next_cursor, total))
+}
+
+// The actor always comes from the token claims, never from the request payload.
+pub async fn ensure_topic_author(
+    executor: impl PgExecutor<'_>,
+    topic_id: Uuid,
+    user_id: Uuid,
+) -> Result<(), AppError> {
+    let author: Uuid = sqlx::query_scalar(
+        r#"
+            select user_id
+            from topics
+            where _id = $1
+            for update
+        "#,
+    )
+    .bind(topic_id)
+    .fetch_optional(executor)
+    .await?
+    .ok_or_else(|| AppError::not_found("Topic not found"))?;
+
+    if author != user_id {
+        return Err(AppError::Forbidden(anyhow!(
+            "Only the author can modify this topic"
+        )));
+    }
+
+    Ok(())
+}
+
+// Comments can be modified by their own author or by the author of the topic they belong to.
+pub async fn ensure_comment_permission(
+    executor: impl PgExecutor<'_>,
+    comment_id: Uuid,
+    user_id: Uuid,
+) -> Result<Uuid, AppError> {
+    let (topic_id, comment_author, topic_author): (Uuid, Uuid, Uuid) = sqlx::query_as(
+        r#"
+            select c.topic, c.user_id, t.user_id
+            from comments c
+            join topics t on t._id = c.topic
+            where c._id = $1
+            for update of c
+        "#,
+    )
+    .bind(comment_id)
+    .fetch_optional(executor)
+    .await?
+    .ok_or_else(|| AppError::not_found("Comment not found"))?;
+
+    if comment_author != user_id && topic_author != user_id {
+        return Err(AppError::Forbidden(anyhow!(
+            "Only the comment author or the topic author can modify this comment"
+        )));
+    }
+
+    Ok(topic_id)
 }
 
 pub async fn _query_tag(pool: &Pool<Postgres>, tag: String) -> Result<Tag, AppError> {
     let tag: Tag = sqlx::query_as(
         r#"
-            select _id, create_at, tag, topics
+            select _id, (select count(*) from topic_tags where tag_id = tags._id) as count, create_at, tag,
+            array(select topic_id from topic_tags where tag_id = tags._id order by create_at) as topics
             from tags
             where tag = $1
         "#,
@@ -62,96 +369,471 @@ pub async fn _query_tag(pool: &Pool<Postgres>, tag: String) -> Result<Tag, AppEr
     Ok(tag)
 }
 
-pub async fn get_user_topics(
+// One query behind the home, tag and profile listings, the filter decides what gets listed
+// and in which order.
+pub async fn list_topics(
     pool: &Pool<Postgres>,
-    page: i32,
-    username: String,
-) -> Result<(Vec<Topic>, i64), AppError> {
-    let offset = (page - 1) * PAGE_SIZE;
+    filter: &TopicFilter,
+    pagination: &Pagination,
+    clip: topic_fmt::Clip,
+    tz: &Tz,
+) -> Result<(Vec<Topic>, Option<String>), AppError> {
+    let sort = filter.sort;
+    let (keyset, order) = match sort.column() {
+        Some(column) => (
+            format!(
+                "($7::timestamptz is null or ({}, t._id) < ($7, $8))",
+                column
+            ),
+            column,
+        ),
+        None => (
+            format!(
+                "($7::bigint is null or ({}, t._id) < ($7, $8))",
+                sort.key_sql()
+            ),
+            "sort_key",
+        ),
+    };
 
-    let topics: Vec<Topic> = sqlx::query_as(
+    let sql = format!(
         r#"
-            with u as
-            (
-                select _id, avatar, bio, birthday, to_char(create_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as create_at, email, favorite, gender, job, nickname, phone, to_char(update_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as update_at, username
-                from users
-                where username = $1
-            )
-            select _id, comments, content, create_at, favorite, tags, title, update_at, user_id, (
-                select row_to_json(u) from u
+            select _id, comments, content, content_html, create_at, (select count(*) from favorites where topic_id = t._id)::int as favorite, slug, {} as sort_key, array(select g.tag from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = t._id order by g.tag) as tags, title, update_at, user_id, (
+                select row_to_json(u) from (
+                    select _id, avatar, bio, birthday, to_char(create_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as create_at, email, array(select topic_id from favorites where user_id = users._id order by create_at) as favorite, gender, job, nickname, phone, to_char(update_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as update_at, username
+                    from users
+                    where _id = t.user_id
+                ) u
             ) as user
             from topics t
-            where t.user_id = (select _id from u)
-            order by update_at desc
-            limit $2 offset $3
-        "#
+            where ($1::text is null or t.user_id = (select _id from users where username = $1))
+                and ($2::text is null or exists(
+                    select 1 from favorites fv
+                    join users fu on fu._id = fv.user_id
+                    where fu.username = $2 and fv.topic_id = t._id
+                ))
+                and (cardinality($3::text[]) = 0 or (
+                    select count(*) from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = t._id and g.tag = any($3)
+                ) = cardinality($3))
+                and (cardinality($4::text[]) = 0 or exists(
+                    select 1 from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = t._id and g.tag = any($4)
+                ))
+                and ($5::date is null or t.create_at >= $5)
+                and ($6::date is null or t.create_at < $6 + 1)
+                and {}
+            order by {} desc, t._id desc
+            limit $9
+        "#,
+        sort.key_sql(),
+        keyset,
+        order
+    );
+    let query = sqlx::query_as::<_, Topic>(&sql)
+        .bind(&filter.author)
+        .bind(&filter.favorited_by)
+        .bind(&filter.all_tags)
+        .bind(&filter.any_tags)
+        .bind(filter.since)
+        .bind(filter.until);
+    let query = match sort.column() {
+        Some(_) => query.bind(pagination.after_at()),
+        None => query.bind(pagination.after_key()),
+    };
+    let query = query
+        .bind(pagination.after_id())
+        .bind(pagination.fetch_limit());
+    let anchor = sort.anchored().then(|| pagination.anchor());
+    let query = match anchor {
+        Some(anchor) => query.bind(anchor),
+        None => query,
+    };
+    let topics = query.fetch_all(pool).await?;
+
+    // time sorts take the key from the row itself, exact to the microsecond like the column
+    let (topics, next_cursor) = pagination.page_at(topics, anchor, |topic| {
+        let key = match sort {
+            TopicSort::Newest => topic.create_at.timestamp_micros(),
+            TopicSort::Updated => topic.update_at.timestamp_micros(),
+            _ => topic.sort_key.unwrap_or_default(),
+        };
+        (key, topic._id)
+    });
+    let topics = topic_fmt::format(topics, clip, tz)?;
+
+    Ok((topics, next_cursor))
+}
+
+pub async fn get_user_topics(
+    pool: &Pool<Postgres>,
+    mut filter: TopicFilter,
+    pagination: &Pagination,
+    username: String,
+    tz: &Tz,
+) -> Result<(Vec<Topic>, Option<String>), AppError> {
+    filter.author = Some(username);
+    list_topics(pool, &filter, pagination, topic_fmt::PROFILE_CLIP, tz).await
+}
+
+pub async fn get_user_favorites(
+    pool: &Pool<Postgres>,
+    mut filter: TopicFilter,
+    pagination: &Pagination,
+    username: String,
+    tz: &Tz,
+) -> Result<(Vec<Topic>, Option<String>), AppError> {
+    filter.favorited_by = Some(username);
+    list_topics(pool, &filter, pagination, topic_fmt::FAVOR_CLIP, tz).await
+}
+
+// Topics can be addressed by their current slug, by one they had before a title change or by
+// id. Slugs are looked up first so one that happens to look like a uuid still resolves.
+pub async fn resolve_topic_id(
+    executor: impl PgExecutor<'_>,
+    topic: &str,
+) -> Result<Uuid, AppError> {
+    let topic_id: Option<Uuid> = sqlx::query_scalar(
+        r#"
+            select _id from topics where slug = $1
+            union all
+            select topic from topic_slugs where slug = $1
+            limit 1
+        "#,
     )
-    .bind(&username)
-    .bind(PAGE_SIZE)
-    .bind(&offset)
-    .fetch_all(pool)
+    .bind(topic)
+    .fetch_optional(executor)
     .await?;
 
-    let total: i64 = sqlx::query_scalar(
+    topic_id
+        .or_else(|| Uuid::parse_str(topic).ok())
+        .ok_or_else(|| AppError::not_found("Topic not found"))
+}
+
+async fn unique_slug(
+    conn: &mut PgConnection,
+    title: &str,
+    topic_id: Option<Uuid>,
+) -> Result<String, AppError> {
+    let base = topic_slug::slugify(title);
+
+    // Two topics picking a slug at the same time would both see it free, slug allocation is
+    // serialized until the transaction ends. `foo-2` may be a suffix of `foo` or a base of its
+    // own, so there is one lock for all of them.
+    sqlx::query("select pg_advisory_xact_lock(hashtext('topic_slug'))")
+        .execute(&mut *conn)
+        .await?;
+
+    let taken: Vec<String> = sqlx::query_scalar(
         r#"
-            with u as (
-                select _id from users where username = $1
-            )
-            select count(*) from topics t
-            where t.user_id = (select _id from u)
+            select slug from topics
+            where (slug = $1 or slug like $1 || '-%') and _id is distinct from $2
+            union
+            select slug from topic_slugs
+            where (slug = $1 or slug like $1 || '-%') and topic is distinct from $2
         "#,
     )
-    .bind(&username)
-    .fetch_one(pool)
+    .bind(&base)
+    .bind(topic_id)
+    .fetch_all(&mut *conn)
     .await?;
 
-    let topics = topic_fmt::format(topics)?;
+    Ok(topic_slug::with_suffix(&base, &taken))
+}
+
+// Regenerates the slug when the title changes, the old one is kept so it still resolves.
+pub async fn update_topic_slug(
+    conn: &mut PgConnection,
+    topic_id: Uuid,
+    title: &str,
+) -> Result<String, AppError> {
+    let (current_title, current_slug): (String, String) = sqlx::query_as(
+        r#"
+            select title, slug
+            from topics
+            where _id = $1
+            for update
+        "#,
+    )
+    .bind(topic_id)
+    .fetch_one(&mut *conn)
+    .await?;
 
-    Ok((topics, total))
+    if current_title == title {
+        return Ok(current_slug);
+    }
+
+    replace_topic_slug(conn, topic_id, title, current_slug).await
 }
 
-pub async fn get_user_favorites(
-    pool: &Pool<Postgres>,
-    page: i32,
-    username: String,
-) -> Result<(Vec<Topic>, i64), AppError> {
-    let offset = (page - 1) * PAGE_SIZE;
+// Moves the topic to a fresh slug for `title`, `current_slug` stays around as an alias.
+pub async fn replace_topic_slug(
+    conn: &mut PgConnection,
+    topic_id: Uuid,
+    title: &str,
+    current_slug: String,
+) -> Result<String, AppError> {
+    let slug = unique_slug(conn, title, Some(topic_id)).await?;
+    if slug == current_slug {
+        return Ok(slug);
+    }
+
+    sqlx::query(
+        r#"
+            delete from topic_slugs
+            where slug = $1 and topic = $2
+        "#,
+    )
+    .bind(&slug)
+    .bind(topic_id)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+            insert into topic_slugs (slug, topic)
+            values ($1, $2)
+            on conflict do nothing
+        "#,
+    )
+    .bind(&current_slug)
+    .bind(topic_id)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+            update topics
+            set slug = $1
+            where _id = $2
+        "#,
+    )
+    .bind(&slug)
+    .bind(topic_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(slug)
+}
 
-    let topics: Vec<Topic> = sqlx::query_as(
+pub async fn create_topic(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    payload: NewTopic,
+) -> Result<Topic, AppError> {
+    payload.validate()?;
+
+    let mut tags = payload.tags.clone();
+    tags = tags
+        .iter()
+        .map(|tag| tag.to_lowercase())
+        .collect::<Vec<String>>();
+    tags.sort();
+    tags.dedup();
+    println!("\nSorted tags: {:?}\n", tags);
+
+    let slug = unique_slug(conn, &payload.title, None).await?;
+    let topic: Topic = sqlx::query_as(
+        r#"
+            insert into topics (content, content_html, slug, title, user_id)
+            values ($1, $2, $3, $4, $5)
+            returning _id, comments, content, content_html, create_at, 0 as favorite, slug, array[]::text[] as tags, title, update_at, user_id
+        "#,
+    )
+    .bind(&payload.content)
+    .bind(markdown::render(&payload.content))
+    .bind(&slug)
+    .bind(&payload.title)
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    tag::set_topic_tags(conn, topic._id, &tags).await?;
+
+    Ok(Topic { tags, ..topic })
+}
+
+// Removes the topic with its comments and pulls it out of every tag, favorites go with it.
+pub async fn delete_topic(conn: &mut PgConnection, topic_id: Uuid) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+            delete from comments
+            where topic = $1
+        "#,
+    )
+    .bind(topic_id)
+    .execute(&mut *conn)
+    .await?;
+
+    tag::set_topic_tags(&mut *conn, topic_id, &[]).await?;
+
+    sqlx::query(
+        r#"
+            delete from topics
+            where _id = $1
+        "#,
+    )
+    .bind(topic_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// Appends a comment (or a reply when `parent_id` is set) to its topic. Replies nest at most
+// `max_depth` levels deep, listings never read past that.
+pub async fn create_comment(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    comment: &NewComment,
+    max_depth: i32,
+) -> Result<Uuid, AppError> {
+    comment.validate()?;
+
+    if let Some(parent_id) = comment.parent_id {
+        let parent_depth = sqlx::query_scalar::<_, Option<i32>>(
+            r#"
+                with recursive ancestors as (
+                    select _id, parent_id, 1 as depth
+                    from comments
+                    where _id = $1 and topic = $2
+                    union all
+                    select c._id, c.parent_id, a.depth + 1
+                    from comments c
+                    join ancestors a on c._id = a.parent_id
+                )
+                select max(depth) from ancestors
+            "#,
+        )
+        .bind(parent_id)
+        .bind(comment.topic)
+        .fetch_one(&mut *conn)
+        .await?
+        .ok_or_else(|| {
+            AppError::field("parent_id", "Parent comment does not belong to this topic")
+        })?;
+
+        if parent_depth >= max_depth {
+            return Err(AppError::field(
+                "parent_id",
+                &format!("Replies can not nest deeper than {} levels", max_depth),
+            ));
+        }
+    }
+
+    let comment_id: Uuid = sqlx::query_scalar(
+        r#"
+            insert into comments (content, content_html, parent_id, topic, user_id)
+            values ($1, $2, $3, $4, $5)
+            returning _id
+        "#,
+    )
+    .bind(&comment.content)
+    .bind(markdown::render(&comment.content))
+    .bind(comment.parent_id)
+    .bind(comment.topic)
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+            update topics
+            set comments = array_append(comments, $1)
+            where _id = $2
+        "#,
+    )
+    .bind(comment_id)
+    .bind(comment.topic)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(comment_id)
+}
+
+// Replies go away with their parent, so the whole subtree is removed from the topic.
+pub async fn delete_comment(
+    conn: &mut PgConnection,
+    comment_id: Uuid,
+    topic_id: Uuid,
+) -> Result<Vec<Uuid>, AppError> {
+    let deleted: Vec<Uuid> = sqlx::query_scalar(
         r#"
-            with u as
-            (
-                select _id, avatar, bio, birthday, to_char(create_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as create_at, email, favorite, gender, job, nickname, phone, to_char(update_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as update_at, username
-                from users
-                where username = $1
+            with recursive subtree as (
+                select _id from comments where _id = $1
+                union all
+                select c._id
+                from comments c
+                join subtree on c.parent_id = subtree._id
             )
-            select _id, comments, content, create_at, favorite, tags, title, update_at, user_id, (
-                select row_to_json(u) from u
-            ) as user
-            from topics t
-            where t._id = any((select favorite from u)::uuid[])
-            order by update_at desc
-            limit $2 offset $3
-        "#
+            delete from comments
+            where _id in (select _id from subtree)
+            returning _id
+        "#,
     )
-    .bind(&username)
-    .bind(PAGE_SIZE)
-    .bind(&offset)
-    .fetch_all(pool)
+    .bind(comment_id)
+    .fetch_all(&mut *conn)
     .await?;
 
-    let favorite: (Vec<Uuid>,) = sqlx::query_as(
+    sqlx::query(
         r#"
-            select favorite from users where username = $1
+            update topics
+            set comments = array(
+                select c from unnest(comments) as c
+                where c <> all($1::uuid[])
+            )
+            where _id = $2
         "#,
     )
-    .bind(&username)
-    .fetch_optional(pool)
+    .bind(&deleted)
+    .bind(topic_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(deleted)
+}
+
+// Favoring is idempotent, favoring twice or removing a missing favorite changes nothing.
+pub async fn set_favorite(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    topic_id: Uuid,
+    favorite: bool,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+            select _id
+            from topics
+            where _id = $1
+        "#,
+    )
+    .bind(topic_id)
+    .fetch_optional(&mut *conn)
     .await?
-    .unwrap_or((vec![],));
+    .ok_or_else(|| AppError::not_found("Topic not found"))?;
 
-    let topics = topic_fmt::format(topics)?;
-    let total = favorite.0.len() as i64;
+    if favorite {
+        sqlx::query(
+            r#"
+                insert into favorites (user_id, topic_id)
+                values ($1, $2)
+                on conflict (user_id, topic_id) do nothing
+            "#,
+        )
+        .bind(user_id)
+        .bind(topic_id)
+        .execute(&mut *conn)
+        .await?;
+    } else {
+        sqlx::query(
+            r#"
+                delete from favorites
+                where user_id = $1 and topic_id = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(topic_id)
+        .execute(&mut *conn)
+        .await?;
+    }
 
-    Ok((topics, total))
+    Ok(())
 }
@@ -0,0 +1,34 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde_json::{json, Value};
+use sqlx::{Pool, Postgres};
+
+use crate::migrate;
+
+fn status(code: StatusCode, msg: &str) -> (StatusCode, Json<Value>) {
+    (code, Json(json!({ "code": code.as_u16(), "msg": msg })))
+}
+
+// Liveness, the process is up and serving requests.
+pub async fn healthz() -> (StatusCode, Json<Value>) {
+    status(StatusCode::OK, "OK")
+}
+
+// Readiness, the database answers and every embedded migration has been applied.
+pub async fn readyz(State(pool): State<Pool<Postgres>>) -> (StatusCode, Json<Value>) {
+    if let Err(err) = sqlx::query("select 1").execute(&pool).await {
+        tracing::warn!("Readiness check failed, database unreachable: {}", err);
+        return status(StatusCode::SERVICE_UNAVAILABLE, "Database unreachable.");
+    }
+
+    match migrate::pending(&pool).await {
+        Ok(pending) if pending.is_empty() => status(StatusCode::OK, "OK"),
+        Ok(pending) => status(
+            StatusCode::SERVICE_UNAVAILABLE,
+            &format!("{} pending migration(s).", pending.len()),
+        ),
+        Err(err) => {
+            tracing::warn!("Readiness check failed, migrations unknown: {}", err);
+            status(StatusCode::SERVICE_UNAVAILABLE, "Migrations not applied.")
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::NaiveDate;
+use serde_json::{json, Map, Value};
+use sqlx::{Pool, Postgres};
+
+use crate::db::Topic;
+
+use super::{
+    utils::{pagination::Pagination, timezone::DisplayTz, topic_fmt},
+    AppError,
+};
+
+fn parse_date(args: &HashMap<String, String>, key: &str) -> Result<Option<NaiveDate>, AppError> {
+    match args.get(key).filter(|date| !date.is_empty()) {
+        Some(date) => Ok(Some(NaiveDate::parse_from_str(date, "%Y-%m-%d")?)),
+        None => Ok(None),
+    }
+}
+
+// Topics match on their title and content or on any of their comments, the snippet comes from
+// the topic content when it matches and from the best matching comment otherwise, it comes back
+// as html in `title_highlight`/`content_highlight` next to the raw clips. Pages are
+// keyed on the rank (scaled to an integer) and the id.
+pub async fn search(
+    State(pool): State<Pool<Postgres>>,
+    DisplayTz(tz): DisplayTz,
+    pagination: Pagination,
+    Query(args): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, AppError> {
+    println!("\nQuery Args: {:?}\n", args);
+    let q = args
+        .get("q")
+        .map(|q| q.trim().to_string())
+        .unwrap_or_default();
+    let tag = args
+        .get("tag")
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| tag.to_lowercase());
+    let author = args.get("author").filter(|author| !author.is_empty());
+    let since = parse_date(&args, "since")?;
+    let until = parse_date(&args, "until")?;
+    let title_options = format!(
+        "HighlightAll=true, StartSel={}, StopSel={}",
+        topic_fmt::MARK_START,
+        topic_fmt::MARK_END
+    );
+    let content_options = format!(
+        "MaxFragments=2, MaxWords=30, MinWords=10, StartSel={}, StopSel={}",
+        topic_fmt::MARK_START,
+        topic_fmt::MARK_END
+    );
+
+    let mut res = Map::new();
+    res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
+    res.insert("msg".to_string(), json!("Search succeed."));
+    res.insert("q".to_string(), json!(&q));
+
+    if q.is_empty() {
+        res.insert("next_cursor".to_string(), json!(null));
+        res.insert("topics".to_string(), json!([]));
+        res.insert("total".to_string(), json!(0));
+        return Ok(Json(json!(res)));
+    }
+
+    let topics: Vec<Topic> = sqlx::query_as(
+        r#"
+            select t._id, t.comments, t.content, t.content_html, t.create_at, (select count(*) from favorites where topic_id = t._id)::int as favorite, t.slug, array(select g.tag from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = t._id order by g.tag) as tags, t.title, t.update_at, t.user_id,
+            ts_headline('english', t.title, tsq, $9) as title_highlight,
+            case
+                when t.search @@ tsq then ts_headline('english', t.content, tsq, $10)
+                else ts_headline('english', c.content, tsq, $10)
+            end as content_highlight,
+            ((ts_rank(t.search, tsq) + coalesce(c.rank, 0) * 0.5) * 1000000)::bigint as sort_key, (
+                select row_to_json(u) from (
+                    select _id, avatar, bio, birthday, to_char(create_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as create_at, email, array(select topic_id from favorites where user_id = users._id order by create_at) as favorite, gender, job, nickname, phone, to_char(update_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as update_at, username
+                    from users
+                    where _id = t.user_id
+                ) u
+            ) as user
+            from topics t
+            cross join websearch_to_tsquery('english', $1) tsq
+            left join lateral (
+                select content, ts_rank(search, tsq) as rank
+                from comments
+                where topic = t._id and search @@ tsq
+                order by rank desc
+                limit 1
+            ) c on true
+            where (t.search @@ tsq or c.content is not null)
+                and ($2::text is null or exists(select 1 from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = t._id and g.tag = $2))
+                and ($3::text is null or t.user_id = (select _id from users where username = $3))
+                and ($4::date is null or t.create_at >= $4::date)
+                and ($5::date is null or t.create_at < $5::date + 1)
+                and ($6::bigint is null or (((ts_rank(t.search, tsq) + coalesce(c.rank, 0) * 0.5) * 1000000)::bigint, t._id) < ($6, $7))
+            order by sort_key desc, t._id desc
+            limit $8
+        "#
+    )
+    .bind(&q)
+    .bind(&tag)
+    .bind(author)
+    .bind(since)
+    .bind(until)
+    .bind(pagination.after_key())
+    .bind(pagination.after_id())
+    .bind(pagination.fetch_limit())
+    .bind(&title_options)
+    .bind(&content_options)
+    .fetch_all(&pool)
+    .await?;
+
+    let total: i64 = sqlx::query_scalar(
+        r#"
+            select count(*)
+            from topics t
+            cross join websearch_to_tsquery('english', $1) tsq
+            where (
+                    t.search @@ tsq
+                    or exists (select 1 from comments c where c.topic = t._id and c.search @@ tsq)
+                )
+                and ($2::text is null or exists(select 1 from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = t._id and g.tag = $2))
+                and ($3::text is null or t.user_id = (select _id from users where username = $3))
+                and ($4::date is null or t.create_at >= $4::date)
+                and ($5::date is null or t.create_at < $5::date + 1)
+        "#,
+    )
+    .bind(&q)
+    .bind(&tag)
+    .bind(author)
+    .bind(since)
+    .bind(until)
+    .fetch_one(&pool)
+    .await?;
+
+    let (topics, next_cursor) = pagination.page(topics, |topic| {
+        (topic.sort_key.unwrap_or_default(), topic._id)
+    });
+    let topics = topic_fmt::format(topics, topic_fmt::SEARCH_CLIP, &tz)?;
+
+    res.insert("next_cursor".to_string(), json!(&next_cursor));
+    res.insert("topics".to_string(), json!(&topics));
+    res.insert("total".to_string(), json!(&total));
+
+    Ok(Json(json!(res)))
+}
@@ -1,22 +1,25 @@
-use std::collections::HashMap;
-
 use axum::{
-    extract::{Path, Query, State},
+    extract::{Path, State},
     http::StatusCode,
     Json,
 };
 use serde_json::{json, Map, Value};
-use sqlx::{Pool, Postgres};
+use sqlx::{PgConnection, Pool, Postgres};
 use uuid::Uuid;
 
-use crate::db::{Tag, Topic};
+use crate::db::Tag;
 
-use super::{utils::topic_fmt, AppError, PAGE_SIZE};
+use super::{
+    common,
+    utils::{pagination::Pagination, timezone::DisplayTz, topic_filter::TopicFilter, topic_fmt},
+    AppError,
+};
 
 pub async fn get_tags(State(pool): State<Pool<Postgres>>) -> Result<Json<Value>, AppError> {
     let tags: Vec<Tag> = sqlx::query_as(
         r#"
-            select _id, create_at, tag, topics, array_length(topics, 1) as count
+            select _id, (select count(*) from topic_tags where tag_id = tags._id) as count, create_at, tag,
+            array(select topic_id from topic_tags where tag_id = tags._id order by create_at) as topics
             from tags
             order by create_at desc
         "#
@@ -34,94 +37,101 @@ pub async fn get_tags(State(pool): State<Pool<Postgres>>) -> Result<Json<Value>,
 
 pub async fn get_topics_by_tag(
     State(pool): State<Pool<Postgres>>,
+    DisplayTz(tz): DisplayTz,
     Path(tag): Path<String>,
-    Query(args): Query<HashMap<String, String>>,
+    mut filter: TopicFilter,
+    pagination: Pagination,
 ) -> Result<Json<Value>, AppError> {
-    println!("\nQuery Args: {:?}\n", args);
-    let page = args
-        .get("page")
-        .unwrap_or(&"1".to_string())
-        .parse::<i32>()?;
-    let offset = (page - 1) * PAGE_SIZE;
+    filter.require_tag(&tag);
 
-    let topics: Vec<Topic> = sqlx::query_as(
-        r#"
-            select _id, comments, content, create_at, favorite, tags, title, update_at, user_id, (
-                select row_to_json(u) from (
-                    select _id, avatar, bio, birthday, to_char(create_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as create_at, email, favorite, gender, job, nickname, phone, to_char(update_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as update_at, username
-                    from users
-                    where _id = t.user_id
-                ) u
-            ) as user
-            from topics t
-            where $1 = any(t.tags::text[])
-            order by update_at desc
-            limit $2 offset $3
-        "#
-    )
-    .bind(&tag)
-    .bind(PAGE_SIZE)
-    .bind(&offset)
-    .fetch_all(&pool)
-    .await?;
-
-    let total: i64 = sqlx::query_scalar(
-        r#"
-            select count(*)
-            from topics t
-            where $1 = any(t.tags::text[])
-        "#,
-    )
-    .bind(&tag)
-    .fetch_optional(&pool)
-    .await?
-    .unwrap_or(0);
-
-    let topics = topic_fmt::format(topics)?;
+    let (topics, next_cursor) =
+        common::list_topics(&pool, &filter, &pagination, topic_fmt::TAG_CLIP, &tz).await?;
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
     res.insert("msg".to_string(), json!("Topics query succeed."));
-    res.insert("page".to_string(), json!(&page));
+    res.insert("next_cursor".to_string(), json!(&next_cursor));
     res.insert("topics".to_string(), json!(&topics));
-    res.insert("total".to_string(), json!(&total));
 
     Ok(Json(json!(res)))
 }
 
-pub async fn update_tags(
-    pool: Pool<Postgres>,
-    tags: Vec<String>,
-    tags_removed: Vec<String>,
+// Points the topic at exactly the given tags, creating the missing ones and
+// dropping the tags no other topic uses anymore. Every tag name the call touches, the ones it
+// links and the ones it may orphan, is locked up front in one ordered statement. Advisory locks
+// cover names that have no row yet, so concurrent updates swapping or creating tags queue up
+// instead of deadlocking, and a cleanup can't delete a tag another transaction is about to use.
+pub async fn set_topic_tags(
+    conn: &mut PgConnection,
     topic_id: Uuid,
+    tags: &[String],
 ) -> Result<(), AppError> {
     sqlx::query(
         r#"
-            select update_tags($1, $2)
-        "#
+            select pg_advisory_xact_lock(hashtext('tags'), hashtext(t.tag))
+            from (
+                select unnest($2::text[]) as tag
+                union
+                select g.tag from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = $1
+            ) t
+            order by hashtext(t.tag)
+        "#,
     )
-    .bind(&tags)
-    .bind(&topic_id)
-    .execute(&pool)
+    .bind(topic_id)
+    .bind(tags)
+    .execute(&mut *conn)
     .await?;
 
     sqlx::query(
         r#"
-            select remove_tags($1, $2)
-        "#
+            insert into tags (tag)
+            select distinct unnest($1::text[])
+            order by 1
+            on conflict (tag) do update set tag = excluded.tag
+        "#,
+    )
+    .bind(tags)
+    .execute(&mut *conn)
+    .await?;
+
+    let removed: Vec<Uuid> = sqlx::query_scalar(
+        r#"
+            delete from topic_tags tt
+            using tags g
+            where tt.tag_id = g._id and tt.topic_id = $1 and g.tag <> all($2::text[])
+            returning tt.tag_id
+        "#,
     )
-    .bind(&tags_removed)
-    .bind(&topic_id)
-    .execute(&pool)
+    .bind(topic_id)
+    .bind(tags)
+    .fetch_all(&mut *conn)
     .await?;
 
     sqlx::query(
         r#"
-            delete from tags
-            where topics = array[]::uuid[]
-        "#
+            insert into topic_tags (topic_id, tag_id)
+            select $1, _id
+            from tags
+            where tag = any($2::text[])
+            on conflict do nothing
+        "#,
+    )
+    .bind(topic_id)
+    .bind(tags)
+    .execute(&mut *conn)
+    .await?;
+
+    // removed tags are locked since the first statement, whoever else links them committed
+    // before that and this fresh statement sees their topic_tags
+    sqlx::query(
+        r#"
+            delete from tags g
+            where g._id = any($1::uuid[])
+                and not exists (select 1 from topic_tags tt where tt.tag_id = g._id)
+        "#,
     )
-    .execute(&pool)
+    .bind(&removed)
+    .execute(&mut *conn)
     .await?;
 
     Ok(())
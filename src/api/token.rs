@@ -0,0 +1,157 @@
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::Local;
+use jsonwebtoken::{encode, Header};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, Pool, Postgres};
+use uuid::Uuid;
+
+use super::{
+    common,
+    utils::jwt::{AuthError, Claims, Keys, REFRESH_TOKEN_TTL},
+    AppError,
+};
+use crate::{
+    db::{RefreshPayload, RefreshToken, User},
+    state::AppState,
+};
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub fn create_access_token(keys: &Keys, user: &User, sid: Uuid) -> Result<String, AuthError> {
+    let claims = Claims::new(user._id, user.nickname.clone(), user.username.clone(), sid);
+    encode(&Header::default(), &claims, &keys.encoding).map_err(|_| AuthError::TokenCreation)
+}
+
+// Only the hash of a refresh token is stored, the plain token is handed out once.
+pub async fn create_refresh_token(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    family: Option<Uuid>,
+) -> Result<(String, RefreshToken), AppError> {
+    let token = hex::encode(rand::random::<[u8; 32]>());
+
+    let refresh_token: RefreshToken = sqlx::query_as(
+        r#"
+            insert into refresh_tokens (expire_at, family, token_hash, user_id)
+            values (now() + make_interval(secs => $1), coalesce($2, gen_random_uuid()), $3, $4)
+            returning _id, expire_at, family, revoke_at, user_id
+        "#,
+    )
+    .bind(REFRESH_TOKEN_TTL as f64)
+    .bind(family)
+    .bind(hash_token(&token))
+    .bind(user_id)
+    .fetch_one(conn)
+    .await?;
+
+    Ok((token, refresh_token))
+}
+
+// Starts a new session (token family) for the user, returns the access and refresh tokens.
+pub async fn issue_tokens(
+    conn: &mut PgConnection,
+    keys: &Keys,
+    user: &User,
+) -> Result<(String, String), AppError> {
+    let (refresh_token, stored) = create_refresh_token(conn, user._id, None).await?;
+    let access_token = create_access_token(keys, user, stored.family)?;
+
+    Ok((access_token, refresh_token))
+}
+
+async fn revoke_family(conn: &mut PgConnection, family: Uuid) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+            update refresh_tokens
+            set revoke_at = now()
+            where family = $1 and revoke_at is null
+        "#,
+    )
+    .bind(family)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn refresh(
+    State(AppState { keys, pool, .. }): State<AppState>,
+    Json(payload): Json<RefreshPayload>,
+) -> Result<Json<Value>, AppError> {
+    if payload.refresh_token.is_empty() {
+        return Err(AppError::Auth(AuthError::MissingCredentials));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let current: RefreshToken = sqlx::query_as(
+        r#"
+            select _id, expire_at, family, revoke_at, user_id
+            from refresh_tokens
+            where token_hash = $1
+            for update
+        "#,
+    )
+    .bind(hash_token(&payload.refresh_token))
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::Auth(AuthError::InvalidToken))?;
+
+    // A rotated token being presented again means it leaked, so the whole family is revoked.
+    if current.revoke_at.is_some() {
+        revoke_family(&mut tx, current.family).await?;
+        tx.commit().await?;
+        return Err(AppError::Auth(AuthError::InvalidToken));
+    }
+
+    if current.expire_at < Local::now() {
+        return Err(AppError::Auth(AuthError::InvalidToken));
+    }
+
+    let (refresh_token, next) =
+        create_refresh_token(&mut tx, current.user_id, Some(current.family)).await?;
+
+    sqlx::query(
+        r#"
+            update refresh_tokens
+            set replaced_by = $1, revoke_at = now()
+            where _id = $2
+        "#,
+    )
+    .bind(next._id)
+    .bind(current._id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let user = common::query_user(&pool, current.user_id).await?;
+    let token = create_access_token(&keys, &user, current.family)?;
+
+    let mut res = Map::new();
+    res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
+    res.insert("msg".to_string(), json!("Token refresh succeed."));
+    res.insert("refresh_token".to_string(), json!(refresh_token));
+    res.insert("token".to_string(), json!(token));
+
+    Ok(Json(json!(res)))
+}
+
+pub async fn logout(
+    claims: Claims,
+    State(pool): State<Pool<Postgres>>,
+) -> Result<Json<Value>, AppError> {
+    println!("\n{:?}\n", claims);
+
+    let mut conn = pool.acquire().await?;
+    revoke_family(&mut conn, claims.sid).await?;
+
+    let mut res = Map::new();
+    res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
+    res.insert("msg".to_string(), json!("User logout succeed."));
+
+    Ok(Json(json!(res)))
+}
@@ -1,20 +1,24 @@
-use std::collections::HashMap;
-
 use axum::{
-    extract::{Path, Query, State},
+    extract::{Path, State},
     http::StatusCode,
     Json,
 };
 use serde_json::{json, Map, Value};
 use sqlx::{Pool, Postgres};
-use uuid::Uuid;
+use validator::Validate;
 
 use super::{
     common, tag,
-    utils::{jwt::Claims, topic_fmt},
-    AppError, PAGE_SIZE,
+    utils::{
+        comment_depth::CommentDepth, jwt::Claims, markdown, pagination::Pagination,
+        timezone::DisplayTz, topic_filter::TopicFilter, topic_fmt,
+    },
+    AppError,
+};
+use crate::{
+    db::{NewComment, NewTopic, Topic, TopicPayload},
+    state::AppState,
 };
-use crate::db::{Comment, NewComment, NewTopic, Topic, TopicPayload};
 
 pub async fn create_topic(
     claims: Claims,
@@ -23,29 +27,9 @@ pub async fn create_topic(
 ) -> Result<Json<Value>, AppError> {
     println!("\n{:?}\n", claims);
 
-    let mut tags = payload.tags.clone();
-    tags = tags
-        .iter()
-        .map(|tag| tag.to_lowercase())
-        .collect::<Vec<String>>();
-    tags.sort();
-    println!("\nSorted tags: {:?}\n", tags);
-
-    let topic: Topic = sqlx::query_as(
-        r#"
-            insert into topics (content, tags, title, user_id)
-            values ($1, $2, $3, $4)
-            returning _id, comments, content, create_at, favorite, tags, title, update_at, user_id
-        "#,
-    )
-    .bind(&payload.content)
-    .bind(&tags)
-    .bind(&payload.title)
-    .bind(&payload.user_id)
-    .fetch_one(&pool)
-    .await?;
-
-    tag::update_tags(pool, tags, vec![], topic._id).await?;
+    let mut tx = pool.begin().await?;
+    let topic = common::create_topic(&mut tx, claims.cuid, payload).await?;
+    tx.commit().await?;
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
@@ -56,18 +40,43 @@ pub async fn create_topic(
 }
 
 pub async fn get_topic(
-    State(pool): State<Pool<Postgres>>,
-    Path(topic_id): Path<Uuid>,
+    State(AppState { config, pool, .. }): State<AppState>,
+    Path(topic): Path<String>,
+    CommentDepth(depth): CommentDepth,
 ) -> Result<Json<Value>, AppError> {
+    let topic_id = common::resolve_topic_id(&pool, &topic).await?;
     let topic = common::query_topic(&pool, topic_id).await?;
+    let (comments, next_cursor, total) =
+        common::query_comments(&pool, topic_id, &Pagination::first(&config), depth).await?;
     let mut topic = json!(&topic);
-    topic["comments"] = topic["comments_arr"].clone();
-    topic["comments_arr"].take();
+    topic["comments"] = json!(&comments);
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
     res.insert("msg".to_string(), json!("Topic query succeed."));
+    res.insert("next_cursor".to_string(), json!(&next_cursor));
     res.insert("topic".to_string(), topic);
+    res.insert("total".to_string(), json!(&total));
+
+    Ok(Json(json!(res)))
+}
+
+pub async fn get_topic_comments(
+    State(pool): State<Pool<Postgres>>,
+    Path(topic): Path<String>,
+    pagination: Pagination,
+    CommentDepth(depth): CommentDepth,
+) -> Result<Json<Value>, AppError> {
+    let topic_id = common::resolve_topic_id(&pool, &topic).await?;
+    let (comments, next_cursor, total) =
+        common::query_comments(&pool, topic_id, &pagination, depth).await?;
+
+    let mut res = Map::new();
+    res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
+    res.insert("msg".to_string(), json!("Topic comments query succeed."));
+    res.insert("comments".to_string(), json!(&comments));
+    res.insert("next_cursor".to_string(), json!(&next_cursor));
+    res.insert("total".to_string(), json!(&total));
 
     Ok(Json(json!(res)))
 }
@@ -75,25 +84,50 @@ pub async fn get_topic(
 pub async fn get_update_topic(
     claims: Claims,
     State(pool): State<Pool<Postgres>>,
-    Path(topic_id): Path<Uuid>,
+    Path(topic): Path<String>,
 ) -> Result<Json<Value>, AppError> {
     println!("\n{:?}\n", claims);
 
+    let topic_id = common::resolve_topic_id(&pool, &topic).await?;
+    common::ensure_topic_author(&pool, topic_id, claims.cuid).await?;
+
     let topic = common::query_topic(&pool, topic_id).await?;
-    let mut topic = json!(&topic);
-    topic["comments"] = topic["comments_arr"].clone();
-    topic["comments_arr"].take();
+    let topic = json!(&topic);
     let user = topic["user"].clone();
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
-    res.insert("msg".to_string(), json!("Topic delete succeed."));
+    res.insert("msg".to_string(), json!("Topic query succeed."));
     res.insert("topic".to_string(), topic);
     res.insert("user".to_string(), user);
 
     Ok(Json(json!(res)))
 }
 
+pub async fn delete_topic(
+    claims: Claims,
+    State(pool): State<Pool<Postgres>>,
+    Path(topic): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    println!("\n{:?}\n", claims);
+
+    let topic_id = common::resolve_topic_id(&pool, &topic).await?;
+    let mut tx = pool.begin().await?;
+
+    common::ensure_topic_author(&mut *tx, topic_id, claims.cuid).await?;
+
+    common::delete_topic(&mut tx, topic_id).await?;
+
+    tx.commit().await?;
+
+    let mut res = Map::new();
+    res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
+    res.insert("msg".to_string(), json!("Topic delete succeed."));
+    res.insert("topic_id".to_string(), json!(&topic_id));
+
+    Ok(Json(json!(res)))
+}
+
 pub async fn topic_update(
     claims: Claims,
     State(pool): State<Pool<Postgres>>,
@@ -101,162 +135,162 @@ pub async fn topic_update(
 ) -> Result<Json<Value>, AppError> {
     println!("\n{:?}\n", claims);
 
+    payload.validate()?;
+
     let mut tags = payload.tags.clone();
     tags = tags
         .iter()
         .map(|tag| tag.to_lowercase())
         .collect::<Vec<String>>();
     tags.sort();
+    tags.dedup();
     println!("\nSorted tags: {:?}\n", tags);
 
+    let mut tx = pool.begin().await?;
+
+    common::ensure_topic_author(&mut *tx, payload._id, claims.cuid).await?;
+    common::update_topic_slug(&mut tx, payload._id, &payload.title).await?;
+
     let topic: Topic = sqlx::query_as(
         r#"
             with u as (
-                select _id, avatar, bio, birthday, to_char(create_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as create_at, email, favorite, gender, job, nickname, phone, to_char(update_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as update_at, username
+                select _id, avatar, bio, birthday, to_char(create_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as create_at, email, array(select topic_id from favorites where user_id = users._id order by create_at) as favorite, gender, job, nickname, phone, to_char(update_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as update_at, username
                 from users
                 where _id = $1
             )
             update topics
-            set content = $2, tags = $3, title = $4
-            where _id = $5
-            returning _id, comments, (
-                select json_agg(cs) from (
-                    select _id, content, create_at, topic, user_id
-                    from comments
-                    where topic = $5
-                    order by create_at desc
-                ) as cs
-            ) as comments_arr, content, create_at, favorite, tags, title, update_at, user_id, (
+            set content = $2, content_html = $3, title = $4
+            where _id = $5 and user_id = $1
+            returning _id, comments, content, content_html, create_at, (select count(*) from favorites where topic_id = topics._id)::int as favorite, slug, array(select g.tag from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = topics._id order by g.tag) as tags, title, update_at, user_id, (
                 select row_to_json(u) from u
             ) as user
         "#,
     )
-    .bind(&payload.user_id)
+    .bind(claims.cuid)
     .bind(&payload.content)
-    .bind(&tags)
+    .bind(markdown::render(&payload.content))
     .bind(&payload.title)
-    .bind(&payload._id)
-    .fetch_one(&pool)
+    .bind(payload._id)
+    .fetch_one(&mut *tx)
     .await?;
 
-    tag::update_tags(pool, tags, payload.tags_removed, payload._id).await?;
+    tag::set_topic_tags(&mut tx, payload._id, &tags).await?;
 
-    let mut topic = json!(&topic);
-    topic["comments"] = topic["comments_arr"].clone();
-    topic["comments_arr"].take();
+    tx.commit().await?;
+
+    let topic = Topic { tags, ..topic };
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
     res.insert("msg".to_string(), json!("Topic update succeed."));
-    res.insert("topic".to_string(), topic);
+    res.insert("topic".to_string(), json!(&topic));
 
     Ok(Json(json!(res)))
 }
 
 pub async fn topic_comment(
     claims: Claims,
-    State(pool): State<Pool<Postgres>>,
+    State(AppState { config, pool, .. }): State<AppState>,
     Json(payload): Json<NewComment>,
 ) -> Result<Json<Value>, AppError> {
     println!("\n{:?}\n", claims);
 
+    let mut tx = pool.begin().await?;
+
+    let comment_id =
+        common::create_comment(&mut tx, claims.cuid, &payload, config.comment_max_depth).await?;
+
     let topic: Topic = sqlx::query_as(
         r#"
-            with
-                u as (
-                    select _id, avatar, bio, birthday, to_char(create_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as create_at, email, favorite, gender, job, nickname, phone, to_char(update_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as update_at, username
+            select _id, comments, content, content_html, create_at, (select count(*) from favorites where topic_id = t._id)::int as favorite, slug, array(select g.tag from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = t._id order by g.tag) as tags, title, update_at, user_id, (
+                select row_to_json(u) from (
+                    select _id, avatar, bio, birthday, to_char(create_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as create_at, email, array(select topic_id from favorites where user_id = users._id order by create_at) as favorite, gender, job, nickname, phone, to_char(update_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as update_at, username
                     from users
-                    where _id = $3
-                ),
-                c as (
-                    insert into comments (content, topic, user_id)
-                    values ($1, $2, $3)
-                    returning _id, content, create_at, topic, user_id
-                )
-            update topics t
-            set comments = array_append(t.comments, c._id)
-            from c
-            where t._id = $2
-            returning t._id, t.comments, t.content, t.create_at, t.favorite, t.tags, t.title, t.update_at, t.user_id, (
-                select row_to_json(u) from u
+                    where _id = t.user_id
+                ) u
             ) as user
+            from topics t
+            where _id = $1
         "#
     )
-    .bind(&payload.content)
-    .bind(&payload.topic)
-    .bind(&payload.user_id)
-    .fetch_one(&pool)
+    .bind(payload.topic)
+    .fetch_one(&mut *tx)
     .await?;
 
-    let comments: Vec<Comment> = sqlx::query_as(
-        r#"
-            select _id, content, create_at, topic, user_id
-            from comments
-            where topic = $1
-            order by create_at desc
-        "#,
-    )
-    .bind(&payload.topic)
-    .fetch_all(&pool)
-    .await?;
+    tx.commit().await?;
 
-    let mut topic = json!(&topic);
-    topic["comments"] = json!(&comments);
+    let comment = common::query_comment(&pool, comment_id).await?;
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
     res.insert("msg".to_string(), json!("Topic comment succeed."));
-    res.insert("updatedTopic".to_string(), topic);
+    res.insert("comment".to_string(), json!(&comment));
+    res.insert("updatedTopic".to_string(), json!(&topic));
 
     Ok(Json(json!(res)))
 }
 
 pub async fn get_topics(
     State(pool): State<Pool<Postgres>>,
-    Query(args): Query<HashMap<String, String>>,
+    DisplayTz(tz): DisplayTz,
+    filter: TopicFilter,
+    pagination: Pagination,
+) -> Result<Json<Value>, AppError> {
+    println!("\n{:?}\n", filter);
+
+    let (topics, next_cursor) =
+        common::list_topics(&pool, &filter, &pagination, topic_fmt::HOME_CLIP, &tz).await?;
+
+    let mut res = Map::new();
+    res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
+    res.insert("msg".to_string(), json!("Topics query succeed."));
+    res.insert("next_cursor".to_string(), json!(&next_cursor));
+    res.insert("topics".to_string(), json!(&topics));
+
+    Ok(Json(json!(res)))
+}
+
+pub async fn get_feed(
+    claims: Claims,
+    State(pool): State<Pool<Postgres>>,
+    DisplayTz(tz): DisplayTz,
+    pagination: Pagination,
 ) -> Result<Json<Value>, AppError> {
-    println!("\nQuery Args: {:?}\n", args);
-    let page = args
-        .get("page")
-        .unwrap_or(&"1".to_string())
-        .parse::<i32>()?;
-    let offset = (page - 1) * PAGE_SIZE;
+    println!("\n{:?}\n", claims);
 
     let topics: Vec<Topic> = sqlx::query_as(
         r#"
-            select _id, comments, content, create_at, favorite, tags, title, update_at, user_id, (
+            select _id, comments, content, content_html, create_at, (select count(*) from favorites where topic_id = t._id)::int as favorite, slug, array(select g.tag from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = t._id order by g.tag) as tags, title, update_at, user_id, (
                 select row_to_json(u) from (
-                    select _id, avatar, bio, birthday, to_char(create_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as create_at, email, favorite, gender, job, nickname, phone, to_char(update_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as update_at, username
+                    select _id, avatar, bio, birthday, to_char(create_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as create_at, email, array(select topic_id from favorites where user_id = users._id order by create_at) as favorite, gender, job, nickname, phone, to_char(update_at at time zone 'utc', 'YYYY-MM-DD"T"HH24:MI:SS"Z"') as update_at, username
                     from users
                     where _id = t.user_id
                 ) u
             ) as user
             from topics t
-            order by update_at desc
-            limit $1 offset $2
+            where t.user_id in (select following_id from follows where follower_id = $1)
+                and ($2::timestamptz is null or (t.update_at, t._id) < ($2, $3))
+            order by t.update_at desc, t._id desc
+            limit $4
         "#
     )
-    .bind(PAGE_SIZE)
-    .bind(&offset)
+    .bind(claims.cuid)
+    .bind(pagination.after_at())
+    .bind(pagination.after_id())
+    .bind(pagination.fetch_limit())
     .fetch_all(&pool)
     .await?;
 
-    let total: i64 = sqlx::query_scalar(
-        r#"
-            select count(*) from topics
-        "#,
-    )
-    .fetch_one(&pool)
-    .await?;
-
-    let topics = topic_fmt::format(topics)?;
+    let (topics, next_cursor) = pagination.page(topics, |topic| {
+        (topic.update_at.timestamp_micros(), topic._id)
+    });
+    let topics = topic_fmt::format(topics, topic_fmt::FEED_CLIP, &tz)?;
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
-    res.insert("msg".to_string(), json!("Topics query succeed."));
-    res.insert("page".to_string(), json!(&page));
+    res.insert("msg".to_string(), json!("Feed query succeed."));
+    res.insert("next_cursor".to_string(), json!(&next_cursor));
     res.insert("topics".to_string(), json!(&topics));
-    res.insert("total".to_string(), json!(&total));
 
     Ok(Json(json!(res)))
 }
@@ -264,24 +298,23 @@ pub async fn get_topics(
 pub async fn get_user_profile(
     claims: Claims,
     State(pool): State<Pool<Postgres>>,
+    DisplayTz(tz): DisplayTz,
     Path(username): Path<String>,
-    Query(args): Query<HashMap<String, String>>,
+    filter: TopicFilter,
+    pagination: Pagination,
 ) -> Result<Json<Value>, AppError> {
     println!("\n{:?}\n", claims);
-    println!("\nQuery Args: {:?}\n", args);
-    let page = args
-        .get("page")
-        .unwrap_or(&"1".to_string())
-        .parse::<i32>()?;
 
-    let (topics, total) = common::get_user_topics(&pool, page, username).await?;
+    let profile = common::query_profile(&pool, &username, Some(claims.cuid)).await?;
+    let (topics, next_cursor) =
+        common::get_user_topics(&pool, filter, &pagination, username, &tz).await?;
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
     res.insert("msg".to_string(), json!("User's profile query succeed."));
-    res.insert("page".to_string(), json!(&page));
+    res.insert("next_cursor".to_string(), json!(&next_cursor));
+    res.insert("profile".to_string(), json!(&profile));
     res.insert("topics".to_string(), json!(&topics));
-    res.insert("total".to_string(), json!(&total));
 
     Ok(Json(json!(res)))
 }
@@ -289,17 +322,15 @@ pub async fn get_user_profile(
 pub async fn get_user_favorites(
     claims: Claims,
     State(pool): State<Pool<Postgres>>,
+    DisplayTz(tz): DisplayTz,
     Path(username): Path<String>,
-    Query(args): Query<HashMap<String, String>>,
+    filter: TopicFilter,
+    pagination: Pagination,
 ) -> Result<Json<Value>, AppError> {
     println!("\n{:?}\n", claims);
-    println!("\nQuery Args: {:?}\n", args);
-    let page = args
-        .get("page")
-        .unwrap_or(&"1".to_string())
-        .parse::<i32>()?;
 
-    let (topics, total) = common::get_user_favorites(&pool, page, username).await?;
+    let (topics, next_cursor) =
+        common::get_user_favorites(&pool, filter, &pagination, username, &tz).await?;
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
@@ -307,9 +338,8 @@ pub async fn get_user_favorites(
         "msg".to_string(),
         json!("User's favorite topics query succeed."),
     );
-    res.insert("page".to_string(), json!(&page));
+    res.insert("next_cursor".to_string(), json!(&next_cursor));
     res.insert("topics".to_string(), json!(&topics));
-    res.insert("total".to_string(), json!(&total));
 
     Ok(Json(json!(res)))
 }
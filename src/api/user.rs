@@ -1,58 +1,57 @@
-use std::collections::HashMap;
-
 use anyhow::anyhow;
 use axum::{
-    extract::{Path, Query, State},
+    extract::{Path, State},
     http::StatusCode,
     Json,
 };
-use jsonwebtoken::{encode, Header};
+use chrono_tz::Tz;
 use serde_json::{json, Map, Value};
 use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+use validator::Validate;
 
 use super::{
-    common,
+    common, token,
     utils::{
-        jwt::{AuthError, AuthPayload, Claims, KEYS},
+        jwt::{AuthError, AuthPayload, Claims},
+        pagination::Pagination,
         password,
+        timezone::DisplayTz,
+        topic_filter::TopicFilter,
     },
-    AppError, PAGE_SIZE,
+    AppError,
 };
 use crate::{
     api::utils::topic_fmt,
-    db::{FavorPayload, NewUser, Topic, User, UserPayload},
+    db::{FavorPayload, NewUser, User, UserPayload},
+    state::AppState,
 };
 
 pub async fn login(
-    State(_pool): State<Pool<Postgres>>,
+    State(AppState { config, keys, pool }): State<AppState>,
     Json(payload): Json<AuthPayload>,
 ) -> Result<Json<Value>, AppError> {
     if payload.email.is_empty() || payload.password.is_empty() {
         return Err(AppError::Auth(AuthError::MissingCredentials));
     }
 
-    let hashed_password = password::hash(payload.password).await?;
-    let user: User = sqlx::query_as(
-        r#"
-            select _id, avatar, bio, birthday, create_at, email, favorite, gender, job, nickname, phone, update_at, username
-            from users
-            where email = $1 and password = $2
-        "#
+    let user = common::authenticate(
+        &pool,
+        config.hash_salt.as_deref(),
+        payload.email,
+        payload.password,
     )
-    .bind(&payload.email)
-    .bind(&hashed_password)
-    .fetch_one(&_pool)
-    .await
-    .map_err(|_| AppError::Auth(AuthError::InvalidCredentials))?;
+    .await?;
 
-    let user_clone = user.clone();
-    let claims = Claims::new(user_clone._id, user_clone.nickname, user_clone.username);
-    let token = encode(&Header::default(), &claims, &KEYS.encoding)
-        .map_err(|_| AuthError::TokenCreation)?;
+    let mut tx = pool.begin().await?;
+    let (token, refresh_token) = token::issue_tokens(&mut tx, &keys, &user).await?;
+
+    tx.commit().await?;
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
     res.insert("msg".to_string(), json!("User login succeed."));
+    res.insert("refresh_token".to_string(), json!(refresh_token));
     res.insert("token".to_string(), json!(token));
     res.insert("user".to_string(), json!(&user));
 
@@ -60,49 +59,20 @@ pub async fn login(
 }
 
 pub async fn register(
-    State(pool): State<Pool<Postgres>>,
+    State(AppState { keys, pool, .. }): State<AppState>,
     Json(new_user): Json<NewUser>,
 ) -> Result<Json<Value>, AppError> {
-    let count: i64 = sqlx::query_scalar(
-        r#"
-            select count(*)
-            from users
-            where email = $1 or username = $2
-        "#,
-    )
-    .bind(&new_user.email)
-    .bind(&new_user.username)
-    .fetch_one(&pool)
-    .await?;
+    let mut tx = pool.begin().await?;
 
-    if count > 0 {
-        return Err(AppError::Duplicate(anyhow!(
-            "Email or username already exsists."
-        )));
-    }
-
-    let hashed_password = password::hash(new_user.password).await?;
-    let user: User = sqlx::query_as(
-        r#"
-            insert into users (email, password, username)
-            values ($1, $2, $3)
-            returning *
-        "#,
-    )
-    .bind(&new_user.email)
-    .bind(&hashed_password)
-    .bind(&new_user.username)
-    .fetch_one(&pool)
-    .await?;
+    let user = common::create_user(&mut tx, new_user).await?;
+    let (token, refresh_token) = token::issue_tokens(&mut tx, &keys, &user).await?;
 
-    let user_clone = user.clone();
-    let claims = Claims::new(user_clone._id, user_clone.nickname, user_clone.username);
-    let token = encode(&Header::default(), &claims, &KEYS.encoding)
-        .map_err(|_| AuthError::TokenCreation)?;
+    tx.commit().await?;
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
     res.insert("msg".to_string(), json!("User register succeed."));
+    res.insert("refresh_token".to_string(), json!(refresh_token));
     res.insert("token".to_string(), json!(token));
     res.insert("user".to_string(), json!(&user));
 
@@ -118,13 +88,14 @@ pub async fn get_user(
 
     let user: User = sqlx::query_as(
         r#"
-            select _id, avatar, bio, birthday, create_at, email, favorite, gender, job, nickname, phone, update_at, username
+            select _id, avatar, bio, birthday, create_at, email, array(select topic_id from favorites where user_id = users._id order by create_at) as favorite, gender, job, nickname, phone, timezone, update_at, username
             from users
             where username = $1
         "#
     ).bind(username)
-    .fetch_one(&pool)
-    .await?;
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| AppError::not_found("User not found"))?;
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
@@ -137,42 +108,32 @@ pub async fn get_user(
 pub async fn get_users(
     claims: Claims,
     State(pool): State<Pool<Postgres>>,
-    Query(args): Query<HashMap<String, String>>,
+    pagination: Pagination,
 ) -> Result<Json<Value>, AppError> {
     println!("\n{:?}\n", claims);
-    println!("\nQuery Args: {:?}\n", args);
-    let page = args
-        .get("page")
-        .unwrap_or(&"1".to_string())
-        .parse::<i32>()?;
-    let offset = (page - 1) * PAGE_SIZE;
 
     let users: Vec<User> = sqlx::query_as(
         r#"
-            select _id, avatar, bio, birthday, create_at, email, favorite, gender, job, nickname, phone, update_at, username
+            select _id, avatar, bio, birthday, create_at, email, array(select topic_id from favorites where user_id = users._id order by create_at) as favorite, gender, job, nickname, phone, timezone, update_at, username
             from users
-            order by create_at desc
-            limit $1 offset $2
+            where ($1::timestamptz is null or (create_at, _id) < ($1, $2))
+            order by create_at desc, _id desc
+            limit $3
         "#
     )
-    .bind(PAGE_SIZE)
-    .bind(&offset)
+    .bind(pagination.after_at())
+    .bind(pagination.after_id())
+    .bind(pagination.fetch_limit())
     .fetch_all(&pool)
     .await?;
 
-    let total: i64 = sqlx::query_scalar(
-        r#"
-            select count(*) from users
-        "#,
-    )
-    .fetch_one(&pool)
-    .await?;
+    let (users, next_cursor) =
+        pagination.page(users, |user| (user.create_at.timestamp_micros(), user._id));
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
     res.insert("msg".to_string(), json!("Users query succeed."));
-    res.insert("page".to_string(), json!(&page));
-    res.insert("total".to_string(), json!(&total));
+    res.insert("next_cursor".to_string(), json!(&next_cursor));
     res.insert("users".to_string(), json!(&users));
 
     Ok(Json(json!(res)))
@@ -201,6 +162,34 @@ pub async fn update_my_settings(
 ) -> Result<Json<Value>, AppError> {
     println!("\n{:?}\n", claims);
 
+    payload.validate()?;
+
+    let hashed_password = match payload.password {
+        Some(password) if !password.is_empty() => Some(password::hash(password).await?),
+        _ => None,
+    };
+
+    let mut tx = pool.begin().await?;
+
+    let count: i64 = sqlx::query_scalar(
+        r#"
+            select count(*)
+            from users
+            where (email = $1 or username = $2) and _id <> $3
+        "#,
+    )
+    .bind(&payload.email)
+    .bind(&payload.username)
+    .bind(claims.cuid)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if count > 0 {
+        return Err(AppError::Conflict(anyhow!(
+            "Email or username already exists"
+        )));
+    }
+
     let user: User = sqlx::query_as(
         r#"
             update users
@@ -212,27 +201,31 @@ pub async fn update_my_settings(
                 gender = case when $5 is not null then $5 else gender end,
                 nickname = case when $6 is not null then $6 else nickname end,
                 job = case when $7 is not null then $7 else job end,
-                password = case when $8 != '' and $8 is not null then $1 else password end,
+                password = case when $8 is not null then $8 else password end,
                 phone = case when $9 is not null then $9 else phone end,
-                username = case when $10 is not null then $10 else username end
-            where _id = $11
-            returning *
+                timezone = case when $10 is not null then nullif($10, '') else timezone end,
+                username = case when $11 is not null then $11 else username end
+            where _id = $12
+            returning _id, avatar, bio, birthday, create_at, email, array(select topic_id from favorites where user_id = users._id order by create_at) as favorite, gender, job, nickname, phone, timezone, update_at, username
         "#,
     )
     .bind(&payload.avatar)
     .bind(&payload.bio)
     .bind(&payload.birthday)
     .bind(&payload.email)
-    .bind(&payload.gender)
+    .bind(payload.gender)
     .bind(&payload.nickname)
     .bind(&payload.job)
-    .bind(&payload.password.unwrap_or("".to_string()))
+    .bind(&hashed_password)
     .bind(&payload.phone)
+    .bind(&payload.timezone)
     .bind(&payload.username)
-    .bind(&payload._id)
-    .fetch_one(&pool)
+    .bind(claims.cuid)
+    .fetch_one(&mut *tx)
     .await?;
 
+    tx.commit().await?;
+
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
     res.insert("msg".to_string(), json!("User settings update succeed."));
@@ -244,23 +237,20 @@ pub async fn update_my_settings(
 pub async fn get_my_topics(
     claims: Claims,
     State(pool): State<Pool<Postgres>>,
-    Query(args): Query<HashMap<String, String>>,
+    DisplayTz(tz): DisplayTz,
+    filter: TopicFilter,
+    pagination: Pagination,
 ) -> Result<Json<Value>, AppError> {
     println!("\n{:?}\n", claims);
-    println!("\nQuery Args: {:?}\n", args);
-    let page = args
-        .get("page")
-        .unwrap_or(&"1".to_string())
-        .parse::<i32>()?;
 
-    let (topics, total) = common::get_user_topics(&pool, page, claims.username).await?;
+    let (topics, next_cursor) =
+        common::get_user_topics(&pool, filter, &pagination, claims.username, &tz).await?;
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
     res.insert("msg".to_string(), json!("User's own topics query succeed."));
-    res.insert("page".to_string(), json!(&page));
+    res.insert("next_cursor".to_string(), json!(&next_cursor));
     res.insert("topics".to_string(), json!(&topics));
-    res.insert("total".to_string(), json!(&total));
 
     Ok(Json(json!(res)))
 }
@@ -268,23 +258,42 @@ pub async fn get_my_topics(
 pub async fn get_my_favorites(
     claims: Claims,
     State(pool): State<Pool<Postgres>>,
-    Query(args): Query<HashMap<String, String>>,
+    DisplayTz(tz): DisplayTz,
+    filter: TopicFilter,
+    pagination: Pagination,
 ) -> Result<Json<Value>, AppError> {
     println!("\n{:?}\n", claims);
-    println!("\nQuery Args: {:?}\n", args);
-    let page = args
-        .get("page")
-        .unwrap_or(&"1".to_string())
-        .parse::<i32>()?;
 
-    let (topics, total) = common::get_user_favorites(&pool, page, claims.username).await?;
+    let (topics, next_cursor) =
+        common::get_user_favorites(&pool, filter, &pagination, claims.username, &tz).await?;
 
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
-    res.insert("msg".to_string(), json!("User's favorite topics query succeed."));
-    res.insert("page".to_string(), json!(&page));
+    res.insert(
+        "msg".to_string(),
+        json!("User's favorite topics query succeed."),
+    );
+    res.insert("next_cursor".to_string(), json!(&next_cursor));
     res.insert("topics".to_string(), json!(&topics));
-    res.insert("total".to_string(), json!(&total));
+
+    Ok(Json(json!(res)))
+}
+
+async fn favor_response(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    topic_id: Uuid,
+    tz: &Tz,
+) -> Result<Json<Value>, AppError> {
+    let topic = common::query_topic(pool, topic_id).await?;
+    let topics = topic_fmt::format(vec![topic], topic_fmt::FAVOR_CLIP, tz)?;
+    let user = common::query_user(pool, user_id).await?;
+
+    let mut res = Map::new();
+    res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
+    res.insert("msg".to_string(), json!("User favor / disfavor succeed."));
+    res.insert("updatedTopic".to_string(), json!(&topics[0]));
+    res.insert("updatedUser".to_string(), json!(&user));
 
     Ok(Json(json!(res)))
 }
@@ -292,76 +301,103 @@ pub async fn get_my_favorites(
 pub async fn favor(
     claims: Claims,
     State(pool): State<Pool<Postgres>>,
+    DisplayTz(tz): DisplayTz,
     Json(payload): Json<FavorPayload>,
 ) -> Result<Json<Value>, AppError> {
     println!("\n{:?}\n", claims);
 
-    let topic: Topic = sqlx::query_as(
+    let mut tx = pool.begin().await?;
+
+    let favored: bool = sqlx::query_scalar(
         r#"
-            with u as (
-                select _id, avatar, bio, birthday, to_char(create_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as create_at, email, favorite, gender, job, nickname, phone, to_char(update_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as update_at, username
-                from users
-                where _id = $1
+            select exists(
+                select 1 from favorites
+                where user_id = $1 and topic_id = $2
             )
-            update topics t
-            set favorite =
-                case
-                    when $2 = any((select favorite from u)::uuid[]) then
-                        favorite - 1
-                    else
-                        favorite + 1
-                end
-            where _id = $2
-            returning _id, comments, content, create_at, favorite, tags, title, update_at, user_id, (
-                select row_to_json(u1) from (
-                    select _id, avatar, bio, birthday, to_char(create_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as create_at, email, favorite, gender, job, nickname, phone, to_char(update_at + interval '8 hours', 'YYYY-MM-DD HH24:MI:SS') as update_at, username
-                    from users
-                    where _id = t.user_id
-                ) u1
-            ) as user
-        "#
+        "#,
     )
-    .bind(&claims.cuid)
-    .bind(&payload.topic_id)
-    .fetch_one(&pool)
+    .bind(claims.cuid)
+    .bind(payload.topic_id)
+    .fetch_one(&mut *tx)
     .await?;
 
-    let topics = topic_fmt::format(vec![topic])?;
+    common::set_favorite(&mut tx, claims.cuid, payload.topic_id, !favored).await?;
 
-    let user: User = sqlx::query_as(
-        r#"
-            with u as (
-                select favorite
-                from users
-                where _id = $1
-            )
-            update users
-            set favorite =
-                case
-                    when $2 = any((select favorite from u)::uuid[]) then
-                        array_remove((select favorite from u)::uuid[], $2)
-                    else
-                        array_append((select favorite from u)::uuid[], $2)
-                end
-            where _id = $1
-            returning _id, avatar, bio, birthday, create_at, email, favorite, gender, job, nickname, phone, update_at, username
-        "#
-    )
-    .bind(&claims.cuid)
-    .bind(&payload.topic_id)
-    .fetch_one(&pool)
-    .await?;
+    tx.commit().await?;
+
+    favor_response(&pool, claims.cuid, payload.topic_id, &tz).await
+}
+
+pub async fn favorite_topic(
+    claims: Claims,
+    State(pool): State<Pool<Postgres>>,
+    DisplayTz(tz): DisplayTz,
+    Path(topic): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    println!("\n{:?}\n", claims);
+
+    let topic_id = common::resolve_topic_id(&pool, &topic).await?;
+    let mut tx = pool.begin().await?;
+    common::set_favorite(&mut tx, claims.cuid, topic_id, true).await?;
+    tx.commit().await?;
+
+    favor_response(&pool, claims.cuid, topic_id, &tz).await
+}
+
+pub async fn unfavorite_topic(
+    claims: Claims,
+    State(pool): State<Pool<Postgres>>,
+    DisplayTz(tz): DisplayTz,
+    Path(topic): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    println!("\n{:?}\n", claims);
+
+    let topic_id = common::resolve_topic_id(&pool, &topic).await?;
+    let mut tx = pool.begin().await?;
+    common::set_favorite(&mut tx, claims.cuid, topic_id, false).await?;
+    tx.commit().await?;
+
+    favor_response(&pool, claims.cuid, topic_id, &tz).await
+}
+
+pub async fn follow(
+    claims: Claims,
+    State(pool): State<Pool<Postgres>>,
+    Path(username): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    println!("\n{:?}\n", claims);
 
-    let mut topic = topics[0].clone();
-    if topic.user_id == claims.cuid {
-        topic.user = Some(json!(&user));
+    if username == claims.username {
+        return Err(AppError::field("username", "can not follow yourself"));
     }
 
+    common::follow_user(&pool, claims.cuid, &username).await?;
+
+    let profile = common::query_profile(&pool, &username, Some(claims.cuid)).await?;
+
     let mut res = Map::new();
     res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
-    res.insert("msg".to_string(), json!("User favor / disfavor succeed."));
-    res.insert("updatedTopic".to_string(), json!(&topic));
-    res.insert("updatedUser".to_string(), json!(&user));
+    res.insert("msg".to_string(), json!("User follow succeed."));
+    res.insert("profile".to_string(), json!(&profile));
+
+    Ok(Json(json!(res)))
+}
+
+pub async fn unfollow(
+    claims: Claims,
+    State(pool): State<Pool<Postgres>>,
+    Path(username): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    println!("\n{:?}\n", claims);
+
+    common::unfollow_user(&pool, claims.cuid, &username).await?;
+
+    let profile = common::query_profile(&pool, &username, Some(claims.cuid)).await?;
+
+    let mut res = Map::new();
+    res.insert("code".to_string(), json!(StatusCode::OK.as_u16()));
+    res.insert("msg".to_string(), json!("User unfollow succeed."));
+    res.insert("profile".to_string(), json!(&profile));
 
     Ok(Json(json!(res)))
 }
@@ -1,4 +1,13 @@
+pub mod comment_depth;
+pub mod comment_tree;
+pub mod cursor;
 pub mod date_fmt;
 pub mod jwt;
+pub mod markdown;
+pub mod pagination;
 pub mod password;
+pub mod timezone;
+pub mod topic_filter;
 pub mod topic_fmt;
+pub mod topic_slug;
+pub mod validate;
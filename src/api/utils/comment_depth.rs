@@ -0,0 +1,36 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Query},
+    http::request::Parts,
+};
+
+use crate::{api::AppError, config::Config};
+
+// `?depth=<n>` on comment listings, how many reply levels to nest under each comment. It
+// defaults to and is capped by `COMMENT_MAX_DEPTH`, the deepest level replies can be posted at.
+pub struct CommentDepth(pub i32);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CommentDepth
+where
+    Arc<Config>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(args) = Query::<HashMap<String, String>>::try_from_uri(&parts.uri)
+            .map_err(|err| AppError::BadRequest(err.into()))?;
+        let max_depth = Arc::<Config>::from_ref(state).comment_max_depth;
+
+        match args.get("depth").filter(|depth| !depth.is_empty()) {
+            Some(depth) => depth
+                .parse::<i32>()
+                .map(|depth| Self(depth.clamp(1, max_depth)))
+                .map_err(|_| AppError::field("depth", "must be a positive integer")),
+            None => Ok(Self(max_depth)),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::db::Comment;
+
+// Nests a flat list of comments under their parents, top level comments keep
+// the order they came in, replies are ordered from oldest to newest.
+pub fn build(comments: Vec<Comment>) -> Vec<Comment> {
+    let mut roots = vec![];
+    let mut children: HashMap<Uuid, Vec<Comment>> = HashMap::new();
+    let ids = comments.iter().map(|c| c._id).collect::<HashSet<Uuid>>();
+
+    comments
+        .into_iter()
+        .for_each(|comment| match comment.parent_id {
+            Some(parent_id) if ids.contains(&parent_id) => {
+                children.entry(parent_id).or_default().push(comment)
+            }
+            _ => roots.push(comment),
+        });
+
+    roots
+        .into_iter()
+        .map(|comment| attach(comment, &mut children))
+        .collect()
+}
+
+fn attach(mut comment: Comment, children: &mut HashMap<Uuid, Vec<Comment>>) -> Comment {
+    let mut replies = children.remove(&comment._id).unwrap_or_default();
+    replies.sort_by_key(|reply| reply.create_at);
+    comment.replies = Some(
+        replies
+            .into_iter()
+            .map(|reply| attach(reply, children))
+            .collect(),
+    );
+    comment
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local, TimeZone};
+
+    use super::*;
+
+    fn comment(id: u128, parent_id: Option<u128>, minute: i64) -> Comment {
+        let at = Local.timestamp_opt(1_700_000_000, 0).unwrap() + Duration::minutes(minute);
+        Comment {
+            _id: Uuid::from_u128(id),
+            content: String::new(),
+            content_html: None,
+            create_at: at,
+            parent_id: parent_id.map(Uuid::from_u128),
+            replies: None,
+            reply_count: None,
+            topic: Uuid::nil(),
+            update_at: at,
+            user_id: Uuid::nil(),
+            user: None,
+        }
+    }
+
+    fn ids(comments: &[Comment]) -> Vec<u128> {
+        comments.iter().map(|c| c._id.as_u128()).collect()
+    }
+
+    #[test]
+    fn nests_replies_under_their_parents() {
+        let tree = build(vec![
+            comment(1, None, 0),
+            comment(2, Some(1), 1),
+            comment(3, Some(2), 2),
+            comment(4, None, 3),
+        ]);
+
+        assert_eq!(ids(&tree), [1, 4]);
+        let replies = tree[0].replies.as_ref().unwrap();
+        assert_eq!(ids(replies), [2]);
+        assert_eq!(ids(replies[0].replies.as_ref().unwrap()), [3]);
+        assert_eq!(tree[1].replies.as_deref().map(ids), Some(vec![]));
+    }
+
+    #[test]
+    fn keeps_root_order_and_sorts_replies_oldest_first() {
+        let tree = build(vec![
+            comment(2, None, 5),
+            comment(1, None, 0),
+            comment(3, Some(2), 9),
+            comment(4, Some(2), 6),
+        ]);
+
+        assert_eq!(ids(&tree), [2, 1]);
+        assert_eq!(ids(tree[0].replies.as_ref().unwrap()), [4, 3]);
+    }
+
+    #[test]
+    fn replies_to_missing_parents_become_roots() {
+        let tree = build(vec![comment(1, None, 0), comment(2, Some(9), 1)]);
+
+        assert_eq!(ids(&tree), [1, 2]);
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::{anyhow, Context};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use uuid::Uuid;
+
+// Cursors are opaque to clients, they wrap the sort key of the last row of a page: a timestamp
+// in microseconds for listings in time order, the ranking score for the other topic sorts.
+// Scores that drift with the clock also carry the instant they were computed at.
+pub fn encode(key: i64, id: Uuid, anchor: Option<i64>) -> String {
+    match anchor {
+        Some(anchor) => URL_SAFE_NO_PAD.encode(format!("{}_{}_{}", key, id, anchor)),
+        None => URL_SAFE_NO_PAD.encode(format!("{}_{}", key, id)),
+    }
+}
+
+pub fn decode(cursor: &str) -> anyhow::Result<(i64, Uuid, Option<i64>)> {
+    let raw = URL_SAFE_NO_PAD.decode(cursor).context("Invalid cursor")?;
+    let raw = String::from_utf8(raw).context("Invalid cursor")?;
+    let mut parts = raw.splitn(3, '_');
+    let key = parts.next().ok_or(anyhow!("Invalid cursor"))?;
+    let id = parts.next().ok_or(anyhow!("Invalid cursor"))?;
+
+    let key = key.parse::<i64>().context("Invalid cursor")?;
+    let id = Uuid::parse_str(id).context("Invalid cursor")?;
+    let anchor = match parts.next() {
+        Some(anchor) => Some(anchor.parse::<i64>().context("Invalid cursor")?),
+        None => None,
+    };
+
+    Ok((key, id, anchor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let id = Uuid::from_u128(0x5941b4b3_a9c3_4c96_a755_e7e3bdbd0684);
+
+        assert_eq!(decode(&encode(42, id, None)).unwrap(), (42, id, None));
+        assert_eq!(decode(&encode(-7, id, None)).unwrap(), (-7, id, None));
+        assert_eq!(
+            decode(&encode(i64::MAX, id, Some(1_700_000_000_000_000))).unwrap(),
+            (i64::MAX, id, Some(1_700_000_000_000_000))
+        );
+    }
+
+    #[test]
+    fn is_url_safe() {
+        let cursor = encode(i64::MIN, Uuid::max(), Some(i64::MAX));
+
+        assert!(cursor
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        let id = Uuid::from_u128(0x5941b4b3_a9c3_4c96_a755_e7e3bdbd0684);
+        let raw = |raw: &str| URL_SAFE_NO_PAD.encode(raw);
+
+        assert!(decode("").is_err());
+        assert!(decode("not base64!").is_err());
+        assert!(decode(&URL_SAFE_NO_PAD.encode([0xff, 0xfe])).is_err());
+        assert!(decode(&raw("42")).is_err());
+        assert!(decode(&raw(&format!("{}", id))).is_err());
+        assert!(decode(&raw(&format!("abc_{}", id))).is_err());
+        assert!(decode(&raw("42_not-a-uuid")).is_err());
+        assert!(decode(&raw(&format!("42_{}_", id))).is_err());
+        assert!(decode(&raw(&format!("42_{}_soon", id))).is_err());
+    }
+}
@@ -1,24 +1,36 @@
-use chrono::{DateTime, FixedOffset, Local, NaiveDateTime};
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
 use serde::{de::Error, Deserialize, Deserializer, Serializer};
 
-const FORMAT: &'static str = "%Y-%m-%d %H:%M:%S";
+// Local strings keep the format timestamps had before they became RFC 3339.
+const LOCAL_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 
 pub fn serialize<S>(date: &DateTime<Local>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    let formatted = format!("{}", date.format(FORMAT));
-    serializer.serialize_str(&formatted)
+    serializer.serialize_str(
+        &date
+            .with_timezone(&Utc)
+            .to_rfc3339_opts(SecondsFormat::Secs, true),
+    )
 }
 
+// Accepts RFC 3339 and, for payloads built against the old format, naive UTC strings.
 pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Local>, D::Error>
 where
     D: Deserializer<'de>,
 {
     let s: String = String::deserialize(deserializer)?;
-    let dt = NaiveDateTime::parse_from_str(&s, FORMAT).map_err(D::Error::custom)?;
-    Ok(DateTime::<Local>::from_naive_utc_and_offset(
-        dt,
-        FixedOffset::east_opt(8 * 3600).unwrap(),
-    ))
+    let dt = match DateTime::parse_from_rfc3339(&s) {
+        Ok(dt) => dt.with_timezone(&Utc),
+        Err(_) => NaiveDateTime::parse_from_str(&s, LOCAL_FORMAT)
+            .map_err(D::Error::custom)?
+            .and_utc(),
+    };
+    Ok(dt.with_timezone(&Local))
+}
+
+pub fn local(date: &DateTime<Local>, tz: &Tz) -> String {
+    date.with_timezone(tz).format(LOCAL_FORMAT).to_string()
 }
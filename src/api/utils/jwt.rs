@@ -1,8 +1,11 @@
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use axum::{
     async_trait,
-    extract::FromRequestParts,
+    extract::{FromRef, FromRequestParts},
     http::{request::Parts, StatusCode},
     response::{IntoResponse, Response},
     Json, RequestPartsExt,
@@ -12,15 +15,16 @@ use axum_extra::{
     TypedHeader,
 };
 use jsonwebtoken::{decode, DecodingKey, EncodingKey, Validation};
-use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use sqlx::{Pool, Postgres};
 use uuid::Uuid;
 
-pub static KEYS: Lazy<Keys> = Lazy::new(|| {
-    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    Keys::new(secret.as_bytes())
-});
+use crate::api::AppError;
+
+// Access tokens are short-lived, clients renew them with a refresh token.
+pub static ACCESS_TOKEN_TTL: u64 = 15 * 60;
+pub static REFRESH_TOKEN_TTL: i64 = 30 * 24 * 60 * 60;
 
 pub struct Keys {
     pub encoding: EncodingKey,
@@ -28,7 +32,7 @@ pub struct Keys {
 }
 
 impl Keys {
-    fn new(secret: &[u8]) -> Self {
+    pub fn new(secret: &[u8]) -> Self {
         Self {
             encoding: EncodingKey::from_secret(secret),
             decoding: DecodingKey::from_secret(secret),
@@ -47,39 +51,73 @@ pub struct Claims {
     pub cuid: Uuid,
     pub exp: usize,
     pub nickname: String,
+    // refresh token family this access token was issued for
+    pub sid: Uuid,
     pub username: String,
 }
 
 impl Claims {
-    pub fn new(cuid: Uuid, nickname: String, username: String) -> Self {
-        let exp = SystemTime::now() + Duration::from_secs(24 * 60 * 60);
+    pub fn new(cuid: Uuid, nickname: String, username: String, sid: Uuid) -> Self {
+        let exp = SystemTime::now() + Duration::from_secs(ACCESS_TOKEN_TTL);
         let exp = exp.duration_since(UNIX_EPOCH).unwrap().as_secs() as usize;
         Self {
             cuid,
             exp,
             nickname,
+            sid,
             username,
         }
     }
+
+    pub fn decode(token: &str, keys: &Keys) -> Result<Self, AuthError> {
+        let token_data = decode::<Claims>(token, &keys.decoding, &Validation::default())
+            .map_err(|_| AuthError::InvalidToken)?;
+
+        Ok(token_data.claims)
+    }
+
+    // Logout revokes the refresh token family, access tokens of that session stop working right
+    // away instead of at their expiry.
+    pub async fn verify(token: &str, keys: &Keys, pool: &Pool<Postgres>) -> Result<Self, AppError> {
+        let claims = Self::decode(token, keys)?;
+
+        let active: bool = sqlx::query_scalar(
+            r#"
+                select exists(select 1 from refresh_tokens where family = $1 and revoke_at is null)
+            "#,
+        )
+        .bind(claims.sid)
+        .fetch_one(pool)
+        .await?;
+        if !active {
+            return Err(AuthError::InvalidToken.into());
+        }
+
+        Ok(claims)
+    }
 }
 
 #[async_trait]
 impl<S> FromRequestParts<S> for Claims
 where
+    Arc<Keys>: FromRef<S>,
+    Pool<Postgres>: FromRef<S>,
     S: Send + Sync,
 {
-    type Rejection = AuthError;
+    type Rejection = AppError;
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let TypedHeader(Authorization(bearer)) = parts
             .extract::<TypedHeader<Authorization<Bearer>>>()
             .await
             .map_err(|_| AuthError::MissingCredentials)?;
 
-        let token_data = decode::<Claims>(bearer.token(), &KEYS.decoding, &Validation::default())
-            .map_err(|_| AuthError::InvalidToken)?;
-
-        Ok(token_data.claims)
+        Claims::verify(
+            bearer.token(),
+            &Arc::<Keys>::from_ref(state),
+            &Pool::<Postgres>::from_ref(state),
+        )
+        .await
     }
 }
 
@@ -91,14 +129,21 @@ pub enum AuthError {
     TokenCreation,
 }
 
-impl IntoResponse for AuthError {
-    fn into_response(self) -> Response {
+impl AuthError {
+    pub fn status_and_msg(&self) -> (StatusCode, String) {
         let (code, msg) = match self {
             Self::InvalidCredentials => (StatusCode::UNAUTHORIZED, "Invalid credentials."),
             Self::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid token."),
             Self::MissingCredentials => (StatusCode::UNAUTHORIZED, "Missing credentials."),
             Self::TokenCreation => (StatusCode::INTERNAL_SERVER_ERROR, "Token creation error."),
         };
+        (code, msg.to_string())
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let (code, msg) = self.status_and_msg();
         let body = Json(json!({ "code": code.as_u16(), "msg": msg }));
         (code, body).into_response()
     }
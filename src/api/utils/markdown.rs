@@ -0,0 +1,81 @@
+use std::{borrow::Cow, collections::HashSet, sync::LazyLock};
+
+use ammonia::Builder;
+use pulldown_cmark::{html, Options, Parser};
+
+// CommonMark (code fences included) plus GitHub style tables and strikethrough.
+pub const OPTIONS: Options = Options::ENABLE_TABLES.union(Options::ENABLE_STRIKETHROUGH);
+
+// Ammonia's defaults drop scripts, event handlers and unsafe urls, code blocks keep their
+// `language-*` class so clients can highlight them. Any other class is dropped, user content
+// must not pick up the site's own styles.
+static SANITIZER: LazyLock<Builder<'static>> = LazyLock::new(|| {
+    let mut builder = Builder::default();
+    builder
+        .add_tag_attributes("code", HashSet::from(["class"]))
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            ("code", "class") => language_class(value).map(Cow::Borrowed),
+            _ => Some(Cow::Borrowed(value)),
+        });
+    builder
+});
+
+fn language_class(class: &str) -> Option<&str> {
+    let language = class.strip_prefix("language-")?;
+    let valid = !language.is_empty()
+        && language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+' | '#'));
+
+    valid.then_some(class)
+}
+
+pub fn render(markdown: &str) -> String {
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(markdown, OPTIONS));
+
+    SANITIZER.clean(&unsafe_html).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_tables_and_code_fences() {
+        let html = render("| a |\n|---|\n| b |\n\n```rust\nfn main() {}\n```");
+        assert!(html.contains("<table>"));
+        assert!(html.contains(r#"<code class="language-rust">fn main() {}"#));
+    }
+
+    #[test]
+    fn strips_scripts() {
+        let html = render("hi <script>alert(1)</script>\n\n<script>\nalert(2)\n</script>");
+        assert!(!html.contains("script"));
+        assert!(!html.contains("alert"));
+    }
+
+    #[test]
+    fn strips_javascript_links() {
+        let html = render("[click](javascript:alert(1)) <a href=\"javascript:alert(2)\">x</a>");
+        assert!(!html.contains("javascript"));
+    }
+
+    #[test]
+    fn strips_event_handlers() {
+        let html = render("<img src=\"x.png\" onerror=\"alert(1)\"> <b onclick=\"alert(2)\">b</b>");
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("onclick"));
+        assert!(html.contains(r#"<img src="x.png">"#));
+    }
+
+    #[test]
+    fn keeps_only_language_classes_on_code() {
+        assert!(render("```c++\nx\n```").contains(r#"class="language-c++""#));
+        let html = render(
+            r#"<code class="admin-only hidden">x</code> <code class="language-x y">y</code>"#,
+        );
+        assert!(!html.contains("class"));
+        assert!(!render(r#"<p class="hidden">x</p>"#).contains("class"));
+    }
+}
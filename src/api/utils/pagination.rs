@@ -0,0 +1,185 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Query},
+    http::request::Parts,
+};
+use chrono::{DateTime, Local};
+use uuid::Uuid;
+
+use super::cursor;
+use crate::{api::AppError, config::Config};
+
+// `?cursor=<opaque>&limit=<n>` on keyset paginated listings. The cursor is the sort key
+// (key, id) of the last row of the previous page, `limit` defaults to `PAGE_SIZE` and is capped
+// by `MAX_PAGE_SIZE`.
+pub struct Pagination {
+    pub after: Option<(i64, Uuid)>,
+    // when the scores of the first page were computed, for sorts that drift with the clock
+    pub anchor: Option<DateTime<Local>>,
+    pub limit: i64,
+}
+
+impl Pagination {
+    pub fn first(config: &Config) -> Self {
+        Self {
+            after: None,
+            anchor: None,
+            limit: config.page_size,
+        }
+    }
+
+    pub fn after_key(&self) -> Option<i64> {
+        self.after.map(|(key, _)| key)
+    }
+
+    // Time ordered listings keep the timestamp in microseconds.
+    pub fn after_at(&self) -> Option<DateTime<Local>> {
+        self.after_key().and_then(from_micros)
+    }
+
+    // The anchor carried over from the previous page, or now on the first one.
+    pub fn anchor(&self) -> DateTime<Local> {
+        self.anchor.unwrap_or_else(Local::now)
+    }
+
+    pub fn after_id(&self) -> Option<Uuid> {
+        self.after.map(|(_, id)| id)
+    }
+
+    // Listings fetch one extra row to know whether another page exists.
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    // Drops the extra row and returns the cursor of the next page, if there is one.
+    pub fn page<T>(
+        &self,
+        rows: Vec<T>,
+        key: impl Fn(&T) -> (i64, Uuid),
+    ) -> (Vec<T>, Option<String>) {
+        self.page_at(rows, None, key)
+    }
+
+    // Same as `page`, the next cursor keeps `anchor` so the following pages score rows against
+    // the same instant.
+    pub fn page_at<T>(
+        &self,
+        mut rows: Vec<T>,
+        anchor: Option<DateTime<Local>>,
+        key: impl Fn(&T) -> (i64, Uuid),
+    ) -> (Vec<T>, Option<String>) {
+        if rows.len() as i64 <= self.limit {
+            return (rows, None);
+        }
+
+        rows.truncate(self.limit as usize);
+        let next_cursor = rows.last().map(|row| {
+            let (key, id) = key(row);
+            cursor::encode(key, id, anchor.map(|at| at.timestamp_micros()))
+        });
+
+        (rows, next_cursor)
+    }
+}
+
+fn from_micros(micros: i64) -> Option<DateTime<Local>> {
+    DateTime::from_timestamp_micros(micros).map(|at| at.with_timezone(&Local))
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Pagination
+where
+    Arc<Config>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(args) = Query::<HashMap<String, String>>::try_from_uri(&parts.uri)
+            .map_err(|err| AppError::BadRequest(err.into()))?;
+
+        let (after, anchor) = match args.get("cursor").filter(|cursor| !cursor.is_empty()) {
+            Some(cursor) => {
+                let (key, id, anchor) = cursor::decode(cursor).map_err(AppError::BadRequest)?;
+                (Some((key, id)), anchor.and_then(from_micros))
+            }
+            None => (None, None),
+        };
+        let config = Arc::<Config>::from_ref(state);
+        let limit = match args.get("limit").filter(|limit| !limit.is_empty()) {
+            Some(limit) => limit.parse::<i64>()?,
+            None => config.page_size,
+        };
+
+        Ok(Self {
+            after,
+            anchor,
+            limit: limit.clamp(1, config.max_page_size),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pagination(limit: i64) -> Pagination {
+        Pagination {
+            after: None,
+            anchor: None,
+            limit,
+        }
+    }
+
+    fn rows(n: u128) -> Vec<(i64, Uuid)> {
+        (1..=n).map(|i| (i as i64, Uuid::from_u128(i))).collect()
+    }
+
+    #[test]
+    fn fetches_one_extra_row() {
+        assert_eq!(pagination(10).fetch_limit(), 11);
+    }
+
+    #[test]
+    fn last_page_has_no_cursor() {
+        let (page, next_cursor) = pagination(3).page(rows(3), |row| *row);
+
+        assert_eq!(page, rows(3));
+        assert_eq!(next_cursor, None);
+    }
+
+    #[test]
+    fn cursor_points_at_the_last_row_kept() {
+        let (page, next_cursor) = pagination(2).page(rows(3), |row| *row);
+
+        assert_eq!(page, rows(2));
+        let (key, id, anchor) = cursor::decode(&next_cursor.unwrap()).unwrap();
+        assert_eq!((key, id, anchor), (2, Uuid::from_u128(2), None));
+    }
+
+    #[test]
+    fn anchored_cursor_keeps_the_anchor() {
+        let at = Local::now();
+        let (_, next_cursor) = pagination(1).page_at(rows(2), Some(at), |row| *row);
+
+        let (_, _, anchor) = cursor::decode(&next_cursor.unwrap()).unwrap();
+        assert_eq!(anchor, Some(at.timestamp_micros()));
+    }
+
+    #[test]
+    fn after_at_reads_the_key_as_microseconds() {
+        let at = Local::now();
+        let pagination = Pagination {
+            after: Some((at.timestamp_micros(), Uuid::nil())),
+            ..pagination(1)
+        };
+
+        assert_eq!(
+            pagination.after_at().map(|at| at.timestamp_micros()),
+            Some(at.timestamp_micros())
+        );
+        assert_eq!(pagination.after_id(), Some(Uuid::nil()));
+    }
+}
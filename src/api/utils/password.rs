@@ -1,15 +1,15 @@
 use anyhow::{anyhow, Context};
+use rand::rngs::OsRng;
 use tokio::task;
 
 use argon2::{
     password_hash::{self, SaltString},
-    Argon2, PasswordHash, PasswordHasher, PasswordVerifier
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
 };
 
 pub async fn hash(password: String) -> anyhow::Result<String> {
     task::spawn_blocking(move || {
-        let secret = std::env::var("HASH_SALT").expect("HASH_SALT must be set");
-        let salt_str = SaltString::encode_b64(&secret.as_bytes()).unwrap();
+        let salt_str = SaltString::generate(&mut OsRng);
 
         anyhow::Ok(
             Argon2::default()
@@ -22,7 +22,7 @@ pub async fn hash(password: String) -> anyhow::Result<String> {
     .context("Panic in password hash().")
 }
 
-pub async fn _verify(password: String, hash: String) -> anyhow::Result<bool> {
+pub async fn verify(password: String, hash: String) -> anyhow::Result<bool> {
     task::spawn_blocking(move || {
         let hash = PasswordHash::new(&hash)
             .map_err(|e| anyhow!(e).context("BUG: Password hash is invalid."))?;
@@ -38,3 +38,19 @@ pub async fn _verify(password: String, hash: String) -> anyhow::Result<bool> {
     .await?
     .context("Panic in password verify().")
 }
+
+// Hashes created before per-user salts were introduced all share the salt
+// derived from `HASH_SALT`, those get re-hashed on the next successful login.
+pub fn needs_rehash(hash: &str, legacy_secret: Option<&str>) -> bool {
+    let Ok(hash) = PasswordHash::new(hash) else {
+        return true;
+    };
+    let Some(secret) = legacy_secret else {
+        return false;
+    };
+    let Ok(legacy_salt) = SaltString::encode_b64(secret.as_bytes()) else {
+        return false;
+    };
+
+    hash.salt.map(|salt| salt.as_str()) == Some(legacy_salt.as_str())
+}
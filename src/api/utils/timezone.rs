@@ -0,0 +1,67 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Query},
+    http::request::Parts,
+};
+use chrono_tz::Tz;
+use sqlx::{Pool, Postgres};
+
+use super::jwt::{Claims, Keys};
+use crate::api::AppError;
+
+// Zone the local time strings (`update_at_str`) are rendered in: the `tz` query argument,
+// then the `X-Timezone` header, then the signed in user's preference, UTC otherwise.
+pub struct DisplayTz(pub Tz);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for DisplayTz
+where
+    Arc<Keys>: FromRef<S>,
+    Pool<Postgres>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let requested = Query::<HashMap<String, String>>::try_from_uri(&parts.uri)
+            .ok()
+            .and_then(|Query(args)| args.get("tz").cloned())
+            .or_else(|| {
+                parts
+                    .headers
+                    .get("x-timezone")
+                    .and_then(|value| value.to_str().ok())
+                    .map(|value| value.to_string())
+            })
+            .filter(|tz| !tz.is_empty());
+
+        if let Some(tz) = requested {
+            return tz.parse::<Tz>().map(Self).map_err(|_| {
+                AppError::field("tz", "must be an IANA time zone name such as Asia/Shanghai")
+            });
+        }
+
+        // Authentication is up to the handler, a missing or bad token only means no preference.
+        let Ok(claims) = Claims::from_request_parts(parts, state).await else {
+            return Ok(Self(Tz::UTC));
+        };
+
+        let timezone: Option<String> = sqlx::query_scalar(
+            r#"
+                select timezone
+                from users
+                where _id = $1
+            "#,
+        )
+        .bind(claims.cuid)
+        .fetch_optional(&Pool::<Postgres>::from_ref(state))
+        .await?
+        .flatten();
+
+        Ok(Self(
+            timezone.and_then(|tz| tz.parse().ok()).unwrap_or(Tz::UTC),
+        ))
+    }
+}
@@ -0,0 +1,268 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use chrono::NaiveDate;
+
+use crate::api::{AppError, FieldErrors};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum TopicSort {
+    Newest,
+    #[default]
+    Updated,
+    MostFavorited,
+    MostCommented,
+    Trending,
+}
+
+impl TopicSort {
+    fn parse(sort: &str) -> Option<Self> {
+        match sort {
+            "newest" => Some(Self::Newest),
+            "updated" => Some(Self::Updated),
+            "most_favorited" => Some(Self::MostFavorited),
+            "most_commented" => Some(Self::MostCommented),
+            "trending" => Some(Self::Trending),
+            _ => None,
+        }
+    }
+
+    // Sorts whose score depends on when it is computed.
+    pub fn anchored(self) -> bool {
+        matches!(self, Self::Trending)
+    }
+
+    // Time sorts keyset on the column itself, so the `(column, _id)` indexes serve them.
+    pub fn column(self) -> Option<&'static str> {
+        match self {
+            Self::Newest => Some("t.create_at"),
+            Self::Updated => Some("t.update_at"),
+            _ => None,
+        }
+    }
+
+    // Bigint sort key of topic `t`, listings keyset on (key, _id). Trending weighs the last
+    // week's favorites and comments against the topic age as of the anchor bound to `$10`, the
+    // cursor carries it so scores hold still while a client scrolls.
+    pub fn key_sql(self) -> &'static str {
+        match self {
+            // keyset on `column()` instead
+            Self::Newest | Self::Updated => "null::bigint",
+            Self::MostFavorited => "(select count(*) from favorites where topic_id = t._id)",
+            Self::MostCommented => "coalesce(cardinality(t.comments), 0)::bigint",
+            Self::Trending => {
+                r#"(
+                    (
+                        (select count(*) from favorites where topic_id = t._id and create_at > $10::timestamptz - interval '7 days') * 2
+                        + (select count(*) from comments where topic = t._id and create_at > $10::timestamptz - interval '7 days')
+                        + 1
+                    ) / power(greatest(extract(epoch from $10::timestamptz - t.create_at), 0) / 3600 + 2, 1.5) * 1000000
+                )::bigint"#
+            }
+        }
+    }
+}
+
+// `?sort=&author=&tag=&tag_match=any|all&favorited_by=&since=&until=` on topic listings.
+// `tag` may repeat or hold a comma separated list, dates are `YYYY-MM-DD` on `create_at`.
+#[derive(Debug, Default)]
+pub struct TopicFilter {
+    pub sort: TopicSort,
+    pub author: Option<String>,
+    // topics have to carry every tag of `all_tags` and at least one of `any_tags`
+    pub all_tags: Vec<String>,
+    pub any_tags: Vec<String>,
+    pub favorited_by: Option<String>,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+}
+
+impl TopicFilter {
+    // Tag listings narrow down to their own tag on top of whatever was asked for.
+    pub fn require_tag(&mut self, tag: &str) {
+        self.all_tags.push(tag.trim().to_lowercase());
+        self.all_tags.sort();
+        self.all_tags.dedup();
+    }
+}
+
+fn parse_date(errors: &mut FieldErrors, key: &str, date: Option<&str>) -> Option<NaiveDate> {
+    let date = date?;
+    match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        Ok(date) => Some(date),
+        Err(_) => {
+            errors
+                .entry(key.to_string())
+                .or_default()
+                .push("must be a valid date formatted as YYYY-MM-DD".to_string());
+            None
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for TopicFilter
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Query(args) = Query::<Vec<(String, String)>>::try_from_uri(&parts.uri)
+            .map_err(|err| AppError::BadRequest(err.into()))?;
+
+        // the last occurrence wins for single valued arguments
+        let arg = |key: &str| {
+            args.iter()
+                .rev()
+                .find(|(k, v)| k == key && !v.trim().is_empty())
+                .map(|(_, v)| v.trim())
+        };
+        let mut errors = FieldErrors::new();
+
+        let sort = match arg("sort") {
+            Some(sort) => TopicSort::parse(sort).unwrap_or_else(|| {
+                errors.entry("sort".to_string()).or_default().push(
+                    "must be one of newest, updated, most_favorited, most_commented, trending"
+                        .to_string(),
+                );
+                TopicSort::default()
+            }),
+            None => TopicSort::default(),
+        };
+
+        let mut tags = args
+            .iter()
+            .filter(|(k, _)| k == "tag")
+            .flat_map(|(_, v)| v.split(','))
+            .map(|tag| tag.trim().to_lowercase())
+            .filter(|tag| !tag.is_empty())
+            .collect::<Vec<String>>();
+        tags.sort();
+        tags.dedup();
+
+        let (all_tags, any_tags) = match arg("tag_match") {
+            None | Some("any") => (vec![], tags),
+            Some("all") => (tags, vec![]),
+            Some(_) => {
+                errors
+                    .entry("tag_match".to_string())
+                    .or_default()
+                    .push("must be either any or all".to_string());
+                (vec![], tags)
+            }
+        };
+
+        let since = parse_date(&mut errors, "since", arg("since"));
+        let until = parse_date(&mut errors, "until", arg("until"));
+
+        if !errors.is_empty() {
+            return Err(AppError::Validation(errors));
+        }
+
+        Ok(Self {
+            sort,
+            author: arg("author").map(|author| author.to_string()),
+            all_tags,
+            any_tags,
+            favorited_by: arg("favorited_by").map(|username| username.to_string()),
+            since,
+            until,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Request;
+
+    use super::*;
+
+    async fn parse(query: &str) -> Result<TopicFilter, FieldErrors> {
+        let request = Request::builder()
+            .uri(format!("/{}", query))
+            .body(())
+            .unwrap();
+        let (mut parts, _) = request.into_parts();
+
+        match TopicFilter::from_request_parts(&mut parts, &()).await {
+            Ok(filter) => Ok(filter),
+            Err(AppError::Validation(errors)) => Err(errors),
+            Err(_) => panic!("expected field errors for {}", query),
+        }
+    }
+
+    #[tokio::test]
+    async fn defaults_to_every_topic_by_update_time() {
+        let filter = parse("").await.unwrap();
+
+        assert!(matches!(filter.sort, TopicSort::Updated));
+        assert!(filter.all_tags.is_empty() && filter.any_tags.is_empty());
+        assert_eq!(filter.author, None);
+        assert_eq!(filter.since, None);
+    }
+
+    #[tokio::test]
+    async fn collects_repeated_and_comma_separated_tags() {
+        let filter = parse("?tag=Rust,go&tag=%20web%20&tag=rust&tag=")
+            .await
+            .unwrap();
+        assert_eq!(filter.any_tags, ["go", "rust", "web"]);
+        assert!(filter.all_tags.is_empty());
+
+        let filter = parse("?tag=rust,go&tag_match=all").await.unwrap();
+        assert_eq!(filter.all_tags, ["go", "rust"]);
+        assert!(filter.any_tags.is_empty());
+    }
+
+    #[tokio::test]
+    async fn last_single_valued_argument_wins() {
+        let filter = parse("?sort=newest&author=q&sort=trending&author=%20")
+            .await
+            .unwrap();
+
+        assert!(matches!(filter.sort, TopicSort::Trending));
+        assert_eq!(filter.author.as_deref(), Some("q"));
+    }
+
+    #[tokio::test]
+    async fn parses_the_date_range() {
+        let filter = parse("?since=2024-07-01&until=2024-07-31").await.unwrap();
+
+        assert_eq!(filter.since, NaiveDate::from_ymd_opt(2024, 7, 1));
+        assert_eq!(filter.until, NaiveDate::from_ymd_opt(2024, 7, 31));
+    }
+
+    #[tokio::test]
+    async fn reports_every_invalid_argument() {
+        let errors = parse("?sort=hot&tag_match=some&since=yesterday&until=2024-02-30")
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            errors.keys().collect::<Vec<_>>(),
+            ["since", "sort", "tag_match", "until"]
+        );
+    }
+
+    #[test]
+    fn require_tag_merges_into_all_tags() {
+        let mut filter = TopicFilter {
+            all_tags: vec!["go".to_string(), "rust".to_string()],
+            ..Default::default()
+        };
+        filter.require_tag(" Rust ");
+        filter.require_tag("Web");
+
+        assert_eq!(filter.all_tags, ["go", "rust", "web"]);
+    }
+
+    #[test]
+    fn only_trending_is_anchored() {
+        assert!(TopicSort::Trending.anchored());
+        assert!(!TopicSort::Updated.anchored());
+        assert!(!TopicSort::MostFavorited.anchored());
+    }
+}
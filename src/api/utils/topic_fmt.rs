@@ -1,23 +1,66 @@
+use chrono_tz::Tz;
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{date_fmt, markdown};
 use crate::db::Topic;
 
-pub fn format(topics: Vec<Topic>) -> Result<Vec<Topic>, anyhow::Error> {
+// Excerpt lengths in graphemes, listings pick the one matching how much room they have.
+#[derive(Clone, Copy)]
+pub struct Clip {
+    pub content: usize,
+    pub title: usize,
+}
+
+pub static HOME_CLIP: Clip = Clip {
+    content: 200,
+    title: 160,
+};
+pub static FEED_CLIP: Clip = Clip {
+    content: 280,
+    title: 160,
+};
+pub static TAG_CLIP: Clip = Clip {
+    content: 200,
+    title: 160,
+};
+pub static SEARCH_CLIP: Clip = Clip {
+    content: 200,
+    title: 160,
+};
+pub static PROFILE_CLIP: Clip = Clip {
+    content: 120,
+    title: 100,
+};
+pub static FAVOR_CLIP: Clip = Clip {
+    content: 120,
+    title: 100,
+};
+
+// `ts_headline` wraps search matches in these private use characters, the snippet is escaped
+// before they turn into `<mark>` tags.
+pub const MARK_START: char = '\u{e000}';
+pub const MARK_END: char = '\u{e001}';
+
+pub fn format(topics: Vec<Topic>, clip: Clip, tz: &Tz) -> Result<Vec<Topic>, anyhow::Error> {
     let mut format_topics = vec![];
     topics.iter().for_each(|topic| {
         let topic = topic.clone();
-        let content_clip = if topic.content.len() > 200 {
-            topic.content[0..=200].to_string() + "..."
-        } else {
-            topic.content.clone()
-        };
-        let title_clip = if topic.title.len() > 160 {
-            topic.title[0..=160].to_string() + "..."
-        } else {
-            topic.title.clone()
-        };
+        // search results also come with snippets, clipped the same way and rendered as html
+        let content_highlight = topic
+            .content_highlight
+            .as_ref()
+            .map(|snippet| highlight(&excerpt(&plain_text(snippet), clip.content)));
+        let title_highlight = topic
+            .title_highlight
+            .as_ref()
+            .map(|snippet| highlight(&excerpt(snippet, clip.title)));
         let format_topic = Topic {
-            content_clip: Some(content_clip),
-            title_clip: Some(title_clip),
-            update_at_str: Some(format!("{}", topic.update_at.format("%Y-%m-%d %H:%M:%S"))),
+            content_clip: Some(excerpt(&plain_text(&topic.content), clip.content)),
+            content_highlight,
+            title_clip: Some(excerpt(&topic.title, clip.title)),
+            title_highlight,
+            update_at_str: Some(date_fmt::local(&topic.update_at, tz)),
             ..topic
         };
 
@@ -26,3 +69,188 @@ pub fn format(topics: Vec<Topic>) -> Result<Vec<Topic>, anyhow::Error> {
 
     Ok(format_topics)
 }
+
+// Keeps the readable text of the markdown, dropping markup, raw html and images.
+pub fn plain_text(source: &str) -> String {
+    let mut text = String::new();
+    let mut in_image = 0;
+    for event in Parser::new_ext(source, markdown::OPTIONS) {
+        match event {
+            Event::Start(Tag::Image { .. }) => in_image += 1,
+            Event::End(TagEnd::Image) => in_image -= 1,
+            Event::Text(t) | Event::Code(t) if in_image == 0 => text.push_str(&t),
+            Event::SoftBreak
+            | Event::HardBreak
+            | Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Heading(_)
+                | TagEnd::BlockQuote
+                | TagEnd::CodeBlock
+                | TagEnd::Item
+                | TagEnd::TableCell,
+            ) => text.push(' '),
+            _ => {}
+        }
+    }
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Cuts on a grapheme boundary and, when that falls inside a word, backs off to the previous
+// space. Text without spaces nearby (CJK, long urls) is cut right at the limit instead.
+pub fn excerpt(text: &str, max: usize) -> String {
+    let Some((cut, _)) = text.grapheme_indices(true).nth(max) else {
+        return text.to_string();
+    };
+
+    let head = &text[..cut];
+    let head = match head.rfind(char::is_whitespace) {
+        Some(space) if !text[cut..].starts_with(char::is_whitespace) && space >= cut / 2 => {
+            &head[..space]
+        }
+        _ => head,
+    };
+
+    format!("{}…", head.trim_end())
+}
+
+// Escapes the snippet for html and swaps the match sentinels for `<mark>` tags, a match cut off
+// at the end of the snippet still gets closed.
+pub fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    let mut open = false;
+    for c in snippet.chars() {
+        match c {
+            MARK_START if !open => {
+                html.push_str("<mark>");
+                open = true;
+            }
+            MARK_END if open => {
+                html.push_str("</mark>");
+                open = false;
+            }
+            MARK_START | MARK_END => {}
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    if open {
+        html.push_str("</mark>");
+    }
+
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_drops_markup() {
+        assert_eq!(
+            plain_text(
+                "# Title\n\nSome **bold** `code` and [a link](https://x.y)\n\n![alt](img.png)"
+            ),
+            "Title Some bold code and a link"
+        );
+        assert_eq!(plain_text("<script>alert(1)</script>\n\ntext"), "text");
+        assert_eq!(
+            plain_text("| a | b |\n|---|---|\n| 中文 | é |"),
+            "a b 中文 é"
+        );
+    }
+
+    #[test]
+    fn excerpt_keeps_short_text() {
+        assert_eq!(excerpt("short", 10), "short");
+        assert_eq!(excerpt("", 10), "");
+    }
+
+    #[test]
+    fn excerpt_backs_off_to_a_word_boundary() {
+        assert_eq!(excerpt("hello wonderful world", 10), "hello…");
+        assert_eq!(excerpt("hello world again", 11), "hello world…");
+    }
+
+    #[test]
+    fn excerpt_cuts_cjk_and_multibyte_on_graphemes() {
+        assert_eq!(excerpt("中文标题中文标题", 3), "中文标…");
+        assert_eq!(excerpt("ééééé", 2), "éé…");
+        // `e` followed by a combining acute accent is one grapheme
+        assert_eq!(excerpt("e\u{301}e\u{301}e\u{301}", 2), "e\u{301}e\u{301}…");
+    }
+
+    #[test]
+    fn excerpt_keeps_emoji_zwj_sequences_whole() {
+        let family = "👨\u{200d}👩\u{200d}👧";
+        let text = format!("{family}{family}{family}");
+        assert_eq!(excerpt(&text, 2), format!("{family}{family}…"));
+        assert_eq!(excerpt("🇨🇳🇺🇸🇬🇧", 1), "🇨🇳…");
+    }
+
+    #[test]
+    fn highlight_escapes_markup_around_matches() {
+        let snippet = format!(
+            "<script>alert(1)</script> {}match{} <img src=x onerror=\"alert('x')\">",
+            MARK_START, MARK_END
+        );
+
+        assert_eq!(
+            highlight(&snippet),
+            "&lt;script&gt;alert(1)&lt;/script&gt; <mark>match</mark> \
+             &lt;img src=x onerror=&quot;alert(&#39;x&#39;)&quot;&gt;"
+        );
+    }
+
+    #[test]
+    fn highlight_closes_cut_off_matches() {
+        assert_eq!(
+            highlight(&format!("a {}b & c", MARK_START)),
+            "a <mark>b &amp; c</mark>"
+        );
+        assert_eq!(highlight(&format!("{}a{}", MARK_END, MARK_END)), "a");
+    }
+
+    #[test]
+    fn format_keeps_clips_raw_next_to_the_highlights() {
+        let now = chrono::Local::now();
+        let topic = Topic {
+            _id: uuid::Uuid::nil(),
+            comments: vec![],
+            content: "Hello 世界 <b>".to_string(),
+            content_clip: None,
+            content_highlight: Some(format!("Hello {}世界{} <b>", MARK_START, MARK_END)),
+            content_html: None,
+            create_at: now,
+            favorite: 0,
+            slug: "hello".to_string(),
+            sort_key: None,
+            tags: vec![],
+            title: "Hello <b>".to_string(),
+            title_clip: None,
+            title_highlight: Some(format!("{}Hello{} <b>", MARK_START, MARK_END)),
+            update_at: now,
+            update_at_str: None,
+            user_id: uuid::Uuid::nil(),
+            user: None,
+        };
+
+        let topic = format(vec![topic], SEARCH_CLIP, &Tz::UTC)
+            .unwrap()
+            .remove(0);
+        assert_eq!(topic.content_clip.as_deref(), Some("Hello 世界"));
+        assert_eq!(
+            topic.content_highlight.as_deref(),
+            Some("Hello <mark>世界</mark>")
+        );
+        assert_eq!(topic.title_clip.as_deref(), Some("Hello <b>"));
+        assert_eq!(
+            topic.title_highlight.as_deref(),
+            Some("<mark>Hello</mark> &lt;b&gt;")
+        );
+    }
+}
@@ -0,0 +1,72 @@
+static MAX_SLUG_LEN: usize = 80;
+
+// Transliterates the title to ascii (so CJK and accented titles still read well in urls)
+// and cuts it on a word boundary.
+pub fn slugify(title: &str) -> String {
+    let slug = slug::slugify(title);
+    let slug = if slug.len() > MAX_SLUG_LEN {
+        let cut = slug[..MAX_SLUG_LEN].rfind('-').unwrap_or(MAX_SLUG_LEN);
+        slug[..cut].to_string()
+    } else {
+        slug
+    };
+
+    if slug.is_empty() {
+        "topic".to_string()
+    } else {
+        slug
+    }
+}
+
+// Picks `base`, or the first free `base-2`, `base-3`, ... given the slugs already taken.
+pub fn with_suffix(base: &str, taken: &[String]) -> String {
+    if !taken.iter().any(|slug| slug == base) {
+        return base.to_string();
+    }
+
+    (2..)
+        .map(|n| format!("{}-{}", base, n))
+        .find(|slug| !taken.contains(slug))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugify_transliterates_titles() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Crème brûlée  "), "creme-brulee");
+        assert_eq!(slugify("Rust 中文"), "rust-zhong-wen");
+    }
+
+    #[test]
+    fn slugify_falls_back_when_nothing_is_left() {
+        assert_eq!(slugify(""), "topic");
+        assert_eq!(slugify("!!! ???"), "topic");
+    }
+
+    #[test]
+    fn slugify_cuts_long_titles_on_a_word_boundary() {
+        let slug = slugify(&"word ".repeat(40));
+        assert!(slug.len() <= MAX_SLUG_LEN);
+        assert!(slug.ends_with("word"));
+
+        let slug = slugify(&"a".repeat(100));
+        assert_eq!(slug.len(), MAX_SLUG_LEN);
+    }
+
+    #[test]
+    fn with_suffix_picks_the_first_free_slug() {
+        let taken = |slugs: &[&str]| slugs.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(with_suffix("rust", &[]), "rust");
+        assert_eq!(with_suffix("rust", &taken(&["rust-2"])), "rust");
+        assert_eq!(with_suffix("rust", &taken(&["rust"])), "rust-2");
+        assert_eq!(
+            with_suffix("rust", &taken(&["rust", "rust-2", "rust-4"])),
+            "rust-3"
+        );
+    }
+}
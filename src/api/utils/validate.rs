@@ -0,0 +1,157 @@
+use std::borrow::Cow;
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use validator::ValidationError;
+
+static MAX_TAG_LEN: usize = 32;
+static MIN_PASSWORD_LEN: usize = 8;
+static MAX_PASSWORD_LEN: usize = 128;
+
+fn invalid(code: &'static str, msg: &'static str) -> ValidationError {
+    ValidationError {
+        message: Some(Cow::from(msg)),
+        ..ValidationError::new(code)
+    }
+}
+
+// Usernames end up in urls (`/profile/:username`), so they stay within a url safe charset.
+pub fn username(username: &str) -> Result<(), ValidationError> {
+    if username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        Ok(())
+    } else {
+        Err(invalid(
+            "username",
+            "may only contain letters, digits, '_' and '-'",
+        ))
+    }
+}
+
+pub fn password(password: &str) -> Result<(), ValidationError> {
+    let len = password.chars().count();
+    if (MIN_PASSWORD_LEN..=MAX_PASSWORD_LEN).contains(&len)
+        && password.chars().any(|c| c.is_alphabetic())
+        && password.chars().any(|c| c.is_ascii_digit())
+    {
+        Ok(())
+    } else {
+        Err(invalid(
+            "password",
+            "must be 8 to 128 characters with at least one letter and one digit",
+        ))
+    }
+}
+
+// Settings updates send an empty password to keep the current one.
+pub fn new_password(new_password: &str) -> Result<(), ValidationError> {
+    if new_password.is_empty() {
+        Ok(())
+    } else {
+        password(new_password)
+    }
+}
+
+// Birthdays are optional, when given they have to be a real `YYYY-MM-DD` date.
+pub fn birthday(birthday: &str) -> Result<(), ValidationError> {
+    if birthday.is_empty() || NaiveDate::parse_from_str(birthday, "%Y-%m-%d").is_ok() {
+        Ok(())
+    } else {
+        Err(invalid(
+            "birthday",
+            "must be a valid date formatted as YYYY-MM-DD",
+        ))
+    }
+}
+
+pub fn timezone(timezone: &str) -> Result<(), ValidationError> {
+    if timezone.is_empty() || timezone.parse::<Tz>().is_ok() {
+        Ok(())
+    } else {
+        Err(invalid(
+            "timezone",
+            "must be an IANA time zone name such as Asia/Shanghai",
+        ))
+    }
+}
+
+pub fn tags(tags: &[String]) -> Result<(), ValidationError> {
+    if tags.iter().all(|tag| {
+        let len = tag.trim().chars().count();
+        len > 0 && len <= MAX_TAG_LEN
+    }) {
+        Ok(())
+    } else {
+        Err(invalid("tags", "each tag must be 1 to 32 characters"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag_list(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn username_stays_url_safe() {
+        assert!(username("jane_doe-42").is_ok());
+        assert!(username("jane doe").is_err());
+        assert!(username("jane/doe").is_err());
+        assert!(username("josé").is_err());
+    }
+
+    #[test]
+    fn password_needs_a_letter_and_a_digit() {
+        assert!(password("abcdefg1").is_ok());
+        assert!(password("пароль12").is_ok());
+        assert!(password("abcdef1").is_err());
+        assert!(password("abcdefgh").is_err());
+        assert!(password("12345678").is_err());
+        assert!(password(&format!("a1{}", "x".repeat(126))).is_ok());
+        assert!(password(&format!("a1{}", "x".repeat(127))).is_err());
+    }
+
+    #[test]
+    fn new_password_may_be_left_empty() {
+        assert!(new_password("").is_ok());
+        assert!(new_password("short1").is_err());
+        assert!(new_password("abcdefg1").is_ok());
+    }
+
+    #[test]
+    fn birthday_is_a_real_date() {
+        assert!(birthday("").is_ok());
+        assert!(birthday("2000-02-29").is_ok());
+        assert!(birthday("2001-02-29").is_err());
+        assert!(birthday("01/02/2000").is_err());
+    }
+
+    #[test]
+    fn timezone_is_an_iana_name() {
+        assert!(timezone("").is_ok());
+        assert!(timezone("Asia/Shanghai").is_ok());
+        assert!(timezone("UTC").is_ok());
+        assert!(timezone("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn tags_are_bounded() {
+        assert!(tags(&[]).is_ok());
+        assert!(tags(&tag_list(&["rust", "中文"])).is_ok());
+        assert!(tags(&tag_list(&["rust", "  "])).is_err());
+        assert!(tags(&tag_list(&[&"字".repeat(32)])).is_ok());
+        assert!(tags(&tag_list(&[&"a".repeat(33)])).is_err());
+    }
+
+    #[test]
+    fn errors_carry_a_message() {
+        let err = username("a b").unwrap_err();
+
+        assert_eq!(err.code, "username");
+        assert!(err.message.is_some());
+    }
+}
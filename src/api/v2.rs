@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::{header::AUTHORIZATION, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+
+pub mod article;
+pub mod profile;
+pub mod tag;
+pub mod user;
+
+use super::{
+    utils::jwt::{AuthError, Claims, Keys},
+    AppError, FieldErrors,
+};
+use crate::{db::Profile, state::AppState};
+
+// RealWorld spec (https://realworld-docs.netlify.app/specifications/backend/endpoints/)
+// compatible routes, mounted under `/api/v2` next to the original API.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/users", post(user::register))
+        .route("/users/login", post(user::login))
+        .route("/user", get(user::get_current_user).put(user::update_user))
+        .route("/profiles/:username", get(profile::get_profile))
+        .route(
+            "/profiles/:username/follow",
+            post(profile::follow).delete(profile::unfollow),
+        )
+        .route(
+            "/articles",
+            get(article::list_articles).post(article::create_article),
+        )
+        .route("/articles/feed", get(article::feed_articles))
+        .route(
+            "/articles/:slug",
+            get(article::get_article)
+                .put(article::update_article)
+                .delete(article::delete_article),
+        )
+        .route(
+            "/articles/:slug/favorite",
+            post(article::favorite_article).delete(article::unfavorite_article),
+        )
+        .route(
+            "/articles/:slug/comments",
+            get(article::get_comments).post(article::add_comment),
+        )
+        .route(
+            "/articles/:slug/comments/:comment_id",
+            delete(article::delete_comment),
+        )
+        .route("/tags", get(tag::get_tags))
+}
+
+// Errors are rendered as `{"errors": {"body": [...]}}` as the spec requires, validation
+// failures are keyed by the failing field instead of `body`.
+pub struct ApiError {
+    status: StatusCode,
+    errors: FieldErrors,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, msg: &str) -> Self {
+        Self {
+            status,
+            errors: FieldErrors::from([("body".to_string(), vec![msg.to_string()])]),
+        }
+    }
+
+    pub fn not_found(msg: &str) -> Self {
+        Self::new(StatusCode::NOT_FOUND, msg)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(json!({ "errors": self.errors }));
+        (self.status, body).into_response()
+    }
+}
+
+impl<E> From<E> for ApiError
+where
+    E: Into<AppError>,
+{
+    fn from(err: E) -> Self {
+        let err = err.into();
+        err.log();
+        let (status, msg) = err.status_and_msg();
+        match err {
+            AppError::Validation(errors) => Self { status, errors },
+            _ => Self::new(status, &msg),
+        }
+    }
+}
+
+// The spec authenticates with `Authorization: Token <jwt>` instead of a bearer token.
+fn token_from_parts(parts: &Parts) -> Result<Option<String>, AuthError> {
+    let Some(header) = parts.headers.get(AUTHORIZATION) else {
+        return Ok(None);
+    };
+
+    header
+        .to_str()
+        .ok()
+        .and_then(|value| value.strip_prefix("Token "))
+        .map(|token| Some(token.trim().to_string()))
+        .ok_or(AuthError::InvalidToken)
+}
+
+pub struct AuthUser {
+    pub claims: Claims,
+    pub token: String,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthUser
+where
+    Arc<Keys>: FromRef<S>,
+    Pool<Postgres>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = token_from_parts(parts)?.ok_or(AuthError::MissingCredentials)?;
+        let claims = Claims::verify(
+            &token,
+            &Arc::<Keys>::from_ref(state),
+            &Pool::<Postgres>::from_ref(state),
+        )
+        .await?;
+
+        Ok(Self { claims, token })
+    }
+}
+
+pub struct MaybeAuthUser(pub Option<Claims>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for MaybeAuthUser
+where
+    Arc<Keys>: FromRef<S>,
+    Pool<Postgres>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match token_from_parts(parts)? {
+            Some(token) => Ok(Self(Some(
+                Claims::verify(
+                    &token,
+                    &Arc::<Keys>::from_ref(state),
+                    &Pool::<Postgres>::from_ref(state),
+                )
+                .await?,
+            ))),
+            None => Ok(Self(None)),
+        }
+    }
+}
+
+impl MaybeAuthUser {
+    pub fn user_id(&self) -> Option<uuid::Uuid> {
+        self.0.as_ref().map(|claims| claims.cuid)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UserBody<T> {
+    pub user: T,
+}
+
+#[derive(Deserialize)]
+pub struct ArticleBody<T> {
+    pub article: T,
+}
+
+#[derive(Deserialize)]
+pub struct CommentBody<T> {
+    pub comment: T,
+}
+
+#[derive(Serialize)]
+pub struct ProfileView {
+    pub bio: String,
+    pub following: bool,
+    pub image: String,
+    pub username: String,
+}
+
+impl From<Profile> for ProfileView {
+    fn from(profile: Profile) -> Self {
+        Self {
+            bio: profile.bio,
+            following: profile.following,
+            image: profile.avatar,
+            username: profile.username,
+        }
+    }
+}
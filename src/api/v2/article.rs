@@ -0,0 +1,576 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{FromRow, Pool, Postgres};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{ApiError, ArticleBody, AuthUser, CommentBody, MaybeAuthUser, ProfileView};
+use crate::{
+    api::{
+        common, tag,
+        utils::{markdown, topic_fmt, validate},
+        AppError,
+    },
+    db::{NewComment, NewTopic, Profile},
+    state::AppState,
+};
+
+static DEFAULT_LIMIT: i64 = 20;
+static MAX_LIMIT: i64 = 100;
+static DESCRIPTION_LEN: usize = 200;
+
+// Every article row is joined with its author, `$1` is the viewer's id (or null).
+const ARTICLE_COLUMNS: &str = r#"
+    select t._id, t.content, t.content_html, t.create_at, t.description,
+        (select count(*) from favorites where topic_id = t._id)::int as favorite, t.slug,
+        array(select g.tag from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = t._id order by g.tag) as tags, t.title, t.update_at,
+        u._id as author_id, u.avatar, u.bio, u.nickname, u.username,
+        exists(
+            select 1 from follows f
+            where f.follower_id = $1 and f.following_id = t.user_id
+        ) as following,
+        exists(
+            select 1 from favorites fv
+            where fv.user_id = $1 and fv.topic_id = t._id
+        ) as favorited
+    from topics t
+    join users u on u._id = t.user_id
+"#;
+
+#[derive(FromRow)]
+struct ArticleRow {
+    _id: Uuid,
+    author_id: Uuid,
+    avatar: String,
+    bio: String,
+    content: String,
+    content_html: Option<String>,
+    create_at: DateTime<Local>,
+    description: Option<String>,
+    favorite: i32,
+    favorited: bool,
+    following: bool,
+    nickname: String,
+    slug: String,
+    tags: Vec<String>,
+    title: String,
+    update_at: DateTime<Local>,
+    username: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArticleView {
+    author: ProfileView,
+    body: String,
+    body_html: Option<String>,
+    created_at: DateTime<Utc>,
+    description: String,
+    favorited: bool,
+    favorites_count: i32,
+    slug: String,
+    tag_list: Vec<String>,
+    title: String,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<ArticleRow> for ArticleView {
+    fn from(row: ArticleRow) -> Self {
+        // Topics created without a description get an excerpt of the body instead.
+        let description = row.description.unwrap_or_else(|| {
+            topic_fmt::excerpt(&topic_fmt::plain_text(&row.content), DESCRIPTION_LEN)
+        });
+
+        Self {
+            author: ProfileView::from(Profile {
+                _id: row.author_id,
+                avatar: row.avatar,
+                bio: row.bio,
+                following: row.following,
+                nickname: row.nickname,
+                username: row.username,
+            }),
+            body: row.content,
+            body_html: row.content_html,
+            created_at: row.create_at.with_timezone(&Utc),
+            description,
+            favorited: row.favorited,
+            favorites_count: row.favorite,
+            slug: row.slug,
+            tag_list: row.tags,
+            title: row.title,
+            updated_at: row.update_at.with_timezone(&Utc),
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct CommentRow {
+    _id: Uuid,
+    author_id: Uuid,
+    avatar: String,
+    bio: String,
+    content: String,
+    content_html: Option<String>,
+    create_at: DateTime<Local>,
+    following: bool,
+    nickname: String,
+    update_at: DateTime<Local>,
+    username: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentView {
+    author: ProfileView,
+    body: String,
+    body_html: Option<String>,
+    created_at: DateTime<Utc>,
+    id: Uuid,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<CommentRow> for CommentView {
+    fn from(row: CommentRow) -> Self {
+        Self {
+            author: ProfileView::from(Profile {
+                _id: row.author_id,
+                avatar: row.avatar,
+                bio: row.bio,
+                following: row.following,
+                nickname: row.nickname,
+                username: row.username,
+            }),
+            body: row.content,
+            body_html: row.content_html,
+            created_at: row.create_at.with_timezone(&Utc),
+            id: row._id,
+            updated_at: row.update_at.with_timezone(&Utc),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    pub author: Option<String>,
+    pub favorited: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub tag: Option<String>,
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct NewArticle {
+    pub body: String,
+    #[validate(length(max = 1024, message = "must be at most 1024 characters"))]
+    pub description: Option<String>,
+    pub tag_list: Option<Vec<String>>,
+    pub title: String,
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateArticle {
+    #[validate(length(min = 1, max = 65536, message = "must be 1 to 65536 characters"))]
+    pub body: Option<String>,
+    #[validate(length(max = 1024, message = "must be at most 1024 characters"))]
+    pub description: Option<String>,
+    #[validate(
+        length(max = 10, message = "must have at most 10 tags"),
+        custom(function = "validate::tags")
+    )]
+    pub tag_list: Option<Vec<String>>,
+    #[validate(length(min = 1, max = 128, message = "must be 1 to 128 characters"))]
+    pub title: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NewArticleComment {
+    pub body: String,
+}
+
+async fn resolve_slug(pool: &Pool<Postgres>, slug: &str) -> Result<Uuid, ApiError> {
+    common::resolve_topic_id(pool, slug)
+        .await
+        .map_err(|err| match err {
+            AppError::NotFound(_) => ApiError::not_found("Article not found."),
+            err => err.into(),
+        })
+}
+
+async fn query_article(
+    pool: &Pool<Postgres>,
+    topic_id: Uuid,
+    viewer_id: Option<Uuid>,
+) -> Result<ArticleView, ApiError> {
+    let row: ArticleRow = sqlx::query_as(&format!("{} where t._id = $2", ARTICLE_COLUMNS))
+        .bind(viewer_id)
+        .bind(topic_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or(ApiError::not_found("Article not found."))?;
+
+    Ok(ArticleView::from(row))
+}
+
+async fn query_article_comment(
+    pool: &Pool<Postgres>,
+    topic_id: Uuid,
+    comment_id: Option<Uuid>,
+    viewer_id: Option<Uuid>,
+) -> Result<Vec<CommentView>, ApiError> {
+    let rows: Vec<CommentRow> = sqlx::query_as(
+        r#"
+            select c._id, c.content, c.content_html, c.create_at, c.update_at,
+                u._id as author_id, u.avatar, u.bio, u.nickname, u.username,
+                exists(
+                    select 1 from follows f
+                    where f.follower_id = $1 and f.following_id = c.user_id
+                ) as following
+            from comments c
+            join users u on u._id = c.user_id
+            where c.topic = $2 and ($3::uuid is null or c._id = $3)
+            order by c.create_at desc
+        "#,
+    )
+    .bind(viewer_id)
+    .bind(topic_id)
+    .bind(comment_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(CommentView::from).collect())
+}
+
+pub async fn list_articles(
+    viewer: MaybeAuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Query(args): Query<ListQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let limit = args.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = args.offset.unwrap_or(0).max(0);
+    // tags are stored lowercased
+    let tag = args
+        .tag
+        .as_deref()
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty());
+
+    let rows: Vec<ArticleRow> = sqlx::query_as(&format!(
+        r#"
+            {}
+            where ($2::text is null or exists(select 1 from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = t._id and g.tag = $2))
+                and ($3::text is null or u.username = $3)
+                and ($4::text is null or exists(
+                    select 1 from favorites fv
+                    join users fu on fu._id = fv.user_id
+                    where fu.username = $4 and fv.topic_id = t._id
+                ))
+            order by t.create_at desc
+            limit $5 offset $6
+        "#,
+        ARTICLE_COLUMNS
+    ))
+    .bind(viewer.user_id())
+    .bind(&tag)
+    .bind(&args.author)
+    .bind(&args.favorited)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&pool)
+    .await?;
+
+    let total: i64 = sqlx::query_scalar(
+        r#"
+            select count(*)
+            from topics t
+            join users u on u._id = t.user_id
+            where ($1::text is null or exists(select 1 from topic_tags tt join tags g on g._id = tt.tag_id where tt.topic_id = t._id and g.tag = $1))
+                and ($2::text is null or u.username = $2)
+                and ($3::text is null or exists(
+                    select 1 from favorites fv
+                    join users fu on fu._id = fv.user_id
+                    where fu.username = $3 and fv.topic_id = t._id
+                ))
+        "#,
+    )
+    .bind(&tag)
+    .bind(&args.author)
+    .bind(&args.favorited)
+    .fetch_one(&pool)
+    .await?;
+
+    let articles = rows.into_iter().map(ArticleView::from).collect::<Vec<_>>();
+
+    Ok(Json(
+        json!({ "articles": articles, "articlesCount": total }),
+    ))
+}
+
+pub async fn feed_articles(
+    auth: AuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Query(args): Query<ListQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let limit = args.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = args.offset.unwrap_or(0).max(0);
+
+    let rows: Vec<ArticleRow> = sqlx::query_as(&format!(
+        r#"
+            {}
+            where t.user_id in (select following_id from follows where follower_id = $1)
+            order by t.create_at desc
+            limit $2 offset $3
+        "#,
+        ARTICLE_COLUMNS
+    ))
+    .bind(auth.claims.cuid)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&pool)
+    .await?;
+
+    let total: i64 = sqlx::query_scalar(
+        r#"
+            select count(*)
+            from topics t
+            where t.user_id in (select following_id from follows where follower_id = $1)
+        "#,
+    )
+    .bind(auth.claims.cuid)
+    .fetch_one(&pool)
+    .await?;
+
+    let articles = rows.into_iter().map(ArticleView::from).collect::<Vec<_>>();
+
+    Ok(Json(
+        json!({ "articles": articles, "articlesCount": total }),
+    ))
+}
+
+pub async fn get_article(
+    viewer: MaybeAuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Path(slug): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let topic_id = resolve_slug(&pool, &slug).await?;
+    let article = query_article(&pool, topic_id, viewer.user_id()).await?;
+
+    Ok(Json(json!({ "article": article })))
+}
+
+pub async fn create_article(
+    auth: AuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Json(payload): Json<ArticleBody<NewArticle>>,
+) -> Result<Json<Value>, ApiError> {
+    let payload = payload.article;
+    payload.validate()?;
+    let description = payload.description;
+    let new_topic = NewTopic {
+        content: payload.body,
+        tags: payload.tag_list.unwrap_or_default(),
+        title: payload.title,
+    };
+
+    let mut tx = pool.begin().await?;
+    let topic = common::create_topic(&mut tx, auth.claims.cuid, new_topic).await?;
+    sqlx::query(
+        r#"
+            update topics
+            set description = $1
+            where _id = $2
+        "#,
+    )
+    .bind(&description)
+    .bind(topic._id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    let article = query_article(&pool, topic._id, Some(auth.claims.cuid)).await?;
+
+    Ok(Json(json!({ "article": article })))
+}
+
+pub async fn update_article(
+    auth: AuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Path(slug): Path<String>,
+    Json(payload): Json<ArticleBody<UpdateArticle>>,
+) -> Result<Json<Value>, ApiError> {
+    let topic_id = resolve_slug(&pool, &slug).await?;
+    let payload = payload.article;
+    payload.validate()?;
+
+    let mut tx = pool.begin().await?;
+
+    common::ensure_topic_author(&mut *tx, topic_id, auth.claims.cuid).await?;
+    if let Some(title) = &payload.title {
+        common::update_topic_slug(&mut tx, topic_id, title).await?;
+    }
+
+    let tags = payload.tag_list.map(|tags| {
+        let mut tags = tags
+            .iter()
+            .map(|tag| tag.to_lowercase())
+            .collect::<Vec<String>>();
+        tags.sort();
+        tags.dedup();
+        tags
+    });
+
+    sqlx::query(
+        r#"
+            update topics
+            set
+                content = coalesce($1, content),
+                content_html = coalesce($2, content_html),
+                description = coalesce($3, description),
+                title = coalesce($4, title)
+            where _id = $5
+        "#,
+    )
+    .bind(&payload.body)
+    .bind(payload.body.as_deref().map(markdown::render))
+    .bind(&payload.description)
+    .bind(&payload.title)
+    .bind(topic_id)
+    .execute(&mut *tx)
+    .await?;
+
+    if let Some(tags) = tags {
+        tag::set_topic_tags(&mut tx, topic_id, &tags).await?;
+    }
+
+    tx.commit().await?;
+
+    let article = query_article(&pool, topic_id, Some(auth.claims.cuid)).await?;
+
+    Ok(Json(json!({ "article": article })))
+}
+
+pub async fn delete_article(
+    auth: AuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Path(slug): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let topic_id = resolve_slug(&pool, &slug).await?;
+    query_article(&pool, topic_id, None).await?;
+
+    let mut tx = pool.begin().await?;
+    common::ensure_topic_author(&mut *tx, topic_id, auth.claims.cuid).await?;
+    common::delete_topic(&mut tx, topic_id).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({})))
+}
+
+pub async fn favorite_article(
+    auth: AuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Path(slug): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    set_favorite(auth, pool, slug, true).await
+}
+
+pub async fn unfavorite_article(
+    auth: AuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Path(slug): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    set_favorite(auth, pool, slug, false).await
+}
+
+async fn set_favorite(
+    auth: AuthUser,
+    pool: Pool<Postgres>,
+    slug: String,
+    favorite: bool,
+) -> Result<Json<Value>, ApiError> {
+    let topic_id = resolve_slug(&pool, &slug).await?;
+    query_article(&pool, topic_id, None).await?;
+
+    let mut tx = pool.begin().await?;
+    common::set_favorite(&mut tx, auth.claims.cuid, topic_id, favorite).await?;
+    tx.commit().await?;
+
+    let article = query_article(&pool, topic_id, Some(auth.claims.cuid)).await?;
+
+    Ok(Json(json!({ "article": article })))
+}
+
+pub async fn get_comments(
+    viewer: MaybeAuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Path(slug): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let topic_id = resolve_slug(&pool, &slug).await?;
+    query_article(&pool, topic_id, None).await?;
+
+    let comments = query_article_comment(&pool, topic_id, None, viewer.user_id()).await?;
+
+    Ok(Json(json!({ "comments": comments })))
+}
+
+pub async fn add_comment(
+    auth: AuthUser,
+    State(AppState { config, pool, .. }): State<AppState>,
+    Path(slug): Path<String>,
+    Json(payload): Json<CommentBody<NewArticleComment>>,
+) -> Result<Json<Value>, ApiError> {
+    let topic_id = resolve_slug(&pool, &slug).await?;
+    query_article(&pool, topic_id, None).await?;
+
+    let new_comment = NewComment {
+        content: payload.comment.body,
+        parent_id: None,
+        topic: topic_id,
+    };
+
+    let mut tx = pool.begin().await?;
+    let comment_id = common::create_comment(
+        &mut tx,
+        auth.claims.cuid,
+        &new_comment,
+        config.comment_max_depth,
+    )
+    .await?;
+    tx.commit().await?;
+
+    let comment = query_article_comment(&pool, topic_id, Some(comment_id), Some(auth.claims.cuid))
+        .await?
+        .pop()
+        .ok_or(ApiError::not_found("Comment not found."))?;
+
+    Ok(Json(json!({ "comment": comment })))
+}
+
+pub async fn delete_comment(
+    auth: AuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Path((slug, comment_id)): Path<(String, String)>,
+) -> Result<Json<Value>, ApiError> {
+    let topic_id = resolve_slug(&pool, &slug).await?;
+    let comment_id =
+        Uuid::parse_str(&comment_id).map_err(|_| ApiError::not_found("Comment not found."))?;
+
+    if query_article_comment(&pool, topic_id, Some(comment_id), None)
+        .await?
+        .is_empty()
+    {
+        return Err(ApiError::not_found("Comment not found."));
+    }
+
+    let mut tx = pool.begin().await?;
+    common::ensure_comment_permission(&mut *tx, comment_id, auth.claims.cuid).await?;
+    common::delete_comment(&mut tx, comment_id, topic_id).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({})))
+}
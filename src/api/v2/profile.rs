@@ -0,0 +1,56 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde_json::{json, Value};
+use sqlx::{Pool, Postgres};
+
+use super::{ApiError, AuthUser, MaybeAuthUser, ProfileView};
+use crate::api::{common, AppError};
+
+async fn profile_view(
+    pool: &Pool<Postgres>,
+    username: &str,
+    viewer_id: Option<uuid::Uuid>,
+) -> Result<Json<Value>, ApiError> {
+    let profile = common::query_profile(pool, username, viewer_id)
+        .await
+        .map_err(|err| match err {
+            AppError::NotFound(_) => ApiError::not_found("Profile not found."),
+            err => err.into(),
+        })?;
+
+    Ok(Json(json!({ "profile": ProfileView::from(profile) })))
+}
+
+pub async fn get_profile(
+    viewer: MaybeAuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Path(username): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    profile_view(&pool, &username, viewer.user_id()).await
+}
+
+pub async fn follow(
+    auth: AuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Path(username): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    if username == auth.claims.username {
+        return Err(AppError::field("username", "can not follow yourself").into());
+    }
+
+    common::follow_user(&pool, auth.claims.cuid, &username).await?;
+
+    profile_view(&pool, &username, Some(auth.claims.cuid)).await
+}
+
+pub async fn unfollow(
+    auth: AuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Path(username): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    common::unfollow_user(&pool, auth.claims.cuid, &username).await?;
+
+    profile_view(&pool, &username, Some(auth.claims.cuid)).await
+}
@@ -0,0 +1,21 @@
+use axum::{extract::State, Json};
+use serde_json::{json, Value};
+use sqlx::{Pool, Postgres};
+
+use super::ApiError;
+
+pub async fn get_tags(State(pool): State<Pool<Postgres>>) -> Result<Json<Value>, ApiError> {
+    let tags: Vec<String> = sqlx::query_scalar(
+        r#"
+            select g.tag
+            from tags g
+            left join topic_tags tt on tt.tag_id = g._id
+            group by g._id
+            order by count(tt.topic_id) desc, g.tag
+        "#,
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(json!({ "tags": tags })))
+}
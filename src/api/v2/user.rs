@@ -0,0 +1,180 @@
+use anyhow::anyhow;
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{Pool, Postgres};
+use validator::Validate;
+
+use super::{ApiError, AuthUser, UserBody};
+use crate::{
+    api::{
+        common, token,
+        utils::{
+            jwt::{AuthError, AuthPayload},
+            password, validate,
+        },
+        AppError,
+    },
+    db::{NewUser, User},
+    state::AppState,
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserView {
+    pub bio: String,
+    pub email: String,
+    pub image: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    pub token: String,
+    pub username: String,
+}
+
+impl UserView {
+    fn new(user: User, token: String, refresh_token: Option<String>) -> Self {
+        Self {
+            bio: user.bio,
+            email: user.email,
+            image: user.avatar,
+            refresh_token,
+            token,
+            username: user.username,
+        }
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateUser {
+    #[validate(length(max = 1024, message = "must be at most 1024 characters"))]
+    pub bio: Option<String>,
+    #[validate(
+        email(message = "must be a valid email address"),
+        length(max = 254, message = "must be at most 254 characters")
+    )]
+    pub email: Option<String>,
+    #[validate(length(max = 2048, message = "must be at most 2048 characters"))]
+    pub image: Option<String>,
+    #[validate(custom(function = "validate::new_password"))]
+    pub password: Option<String>,
+    #[validate(
+        length(min = 3, max = 32, message = "must be 3 to 32 characters"),
+        custom(function = "validate::username")
+    )]
+    pub username: Option<String>,
+}
+
+pub async fn login(
+    State(AppState { config, keys, pool }): State<AppState>,
+    Json(payload): Json<UserBody<AuthPayload>>,
+) -> Result<Json<Value>, ApiError> {
+    let payload = payload.user;
+    if payload.email.is_empty() || payload.password.is_empty() {
+        return Err(AuthError::MissingCredentials.into());
+    }
+
+    let user = common::authenticate(
+        &pool,
+        config.hash_salt.as_deref(),
+        payload.email,
+        payload.password,
+    )
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    let (token, refresh_token) = token::issue_tokens(&mut tx, &keys, &user).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(
+        json!({ "user": UserView::new(user, token, Some(refresh_token)) }),
+    ))
+}
+
+pub async fn register(
+    State(AppState { keys, pool, .. }): State<AppState>,
+    Json(payload): Json<UserBody<NewUser>>,
+) -> Result<Json<Value>, ApiError> {
+    let mut tx = pool.begin().await?;
+
+    let user = common::create_user(&mut tx, payload.user).await?;
+    let (token, refresh_token) = token::issue_tokens(&mut tx, &keys, &user).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(
+        json!({ "user": UserView::new(user, token, Some(refresh_token)) }),
+    ))
+}
+
+pub async fn get_current_user(
+    auth: AuthUser,
+    State(pool): State<Pool<Postgres>>,
+) -> Result<Json<Value>, ApiError> {
+    let user = common::query_user(&pool, auth.claims.cuid).await?;
+
+    Ok(Json(
+        json!({ "user": UserView::new(user, auth.token, None) }),
+    ))
+}
+
+pub async fn update_user(
+    auth: AuthUser,
+    State(pool): State<Pool<Postgres>>,
+    Json(payload): Json<UserBody<UpdateUser>>,
+) -> Result<Json<Value>, ApiError> {
+    let payload = payload.user;
+    payload.validate()?;
+
+    let mut tx = pool.begin().await?;
+
+    let count: i64 = sqlx::query_scalar(
+        r#"
+            select count(*)
+            from users
+            where (email = $1 or username = $2) and _id <> $3
+        "#,
+    )
+    .bind(&payload.email)
+    .bind(&payload.username)
+    .bind(auth.claims.cuid)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if count > 0 {
+        return Err(AppError::Conflict(anyhow!("Email or username already exists")).into());
+    }
+
+    let hashed_password = match payload.password {
+        Some(password) if !password.is_empty() => Some(password::hash(password).await?),
+        _ => None,
+    };
+
+    let user: User = sqlx::query_as(
+        r#"
+            update users
+            set
+                avatar = coalesce($1, avatar),
+                bio = coalesce($2, bio),
+                email = coalesce($3, email),
+                password = coalesce($4, password),
+                username = coalesce($5, username)
+            where _id = $6
+            returning _id, avatar, bio, birthday, create_at, email, array(select topic_id from favorites where user_id = users._id order by create_at) as favorite, gender, job, nickname, phone, timezone, update_at, username
+        "#,
+    )
+    .bind(&payload.image)
+    .bind(&payload.bio)
+    .bind(&payload.email)
+    .bind(&hashed_password)
+    .bind(&payload.username)
+    .bind(auth.claims.cuid)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(
+        json!({ "user": UserView::new(user, auth.token, None) }),
+    ))
+}
@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use clap::{Parser, Subcommand};
+
+use crate::migrate::MigrateAction;
+
+// Command line flags, each one falls back to an environment variable and then to a default.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Maximum number of pooled database connections
+    #[arg(long, env = "DB_MAX_CONNECTIONS", default_value_t = 10)]
+    pub db_max_connections: u32,
+
+    /// Connections the pool keeps open even when idle
+    #[arg(long, env = "DB_MIN_CONNECTIONS", default_value_t = 0)]
+    pub db_min_connections: u32,
+
+    /// Seconds to wait for a free connection before a query fails
+    #[arg(long, env = "DB_ACQUIRE_TIMEOUT", default_value_t = 30)]
+    pub db_acquire_timeout: u64,
+
+    /// Seconds an unused connection stays open, 0 keeps it forever
+    #[arg(long, env = "DB_IDLE_TIMEOUT", default_value_t = 600)]
+    pub db_idle_timeout: u64,
+
+    /// Connection attempts at startup before giving up
+    #[arg(long, env = "DB_CONNECT_RETRIES", default_value_t = 5)]
+    pub db_connect_retries: u32,
+
+    /// Address the server listens on
+    #[arg(long, env = "HOST", default_value = "127.0.0.1")]
+    pub host: String,
+
+    /// Deepest level replies can nest to under a top level comment
+    #[arg(long, env = "COMMENT_MAX_DEPTH", default_value_t = 5)]
+    pub comment_max_depth: u32,
+
+    /// Port the server listens on
+    #[arg(long, env = "PORT", default_value_t = 3001)]
+    pub port: u16,
+
+    /// Page size of paginated listings when clients don't pass a `limit`
+    #[arg(long, env = "PAGE_SIZE", default_value_t = 10)]
+    pub page_size: u32,
+
+    /// Largest `limit` clients can ask for on paginated listings
+    #[arg(long, env = "MAX_PAGE_SIZE", default_value_t = 50)]
+    pub max_page_size: u32,
+
+    /// Apply pending migrations before the server starts
+    #[arg(long, env = "MIGRATE_ON_START")]
+    pub migrate_on_start: bool,
+
+    /// Seconds in-flight requests get to finish after SIGTERM/SIGINT
+    #[arg(long, env = "SHUTDOWN_TIMEOUT", default_value_t = 30)]
+    pub shutdown_timeout: u64,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Apply, revert or inspect the embedded database migrations
+    Migrate {
+        #[command(subcommand)]
+        action: MigrateAction,
+    },
+}
+
+// Database connection and pool settings, all the `migrate` subcommand needs.
+#[derive(Clone, Debug)]
+pub struct DbConfig {
+    pub acquire_timeout: Duration,
+    pub connect_retries: u32,
+    pub database_url: String,
+    pub idle_timeout: Option<Duration>,
+    pub max_connections: u32,
+    pub min_connections: u32,
+}
+
+impl DbConfig {
+    pub fn load(args: &Args) -> anyhow::Result<Self> {
+        let database_url = required(&["DATABASE_URL"])?.remove(0);
+
+        if args.db_max_connections == 0 || args.db_min_connections > args.db_max_connections {
+            bail!(
+                "DB_MAX_CONNECTIONS must be positive and at least DB_MIN_CONNECTIONS ({} > {}).",
+                args.db_min_connections,
+                args.db_max_connections
+            );
+        }
+
+        Ok(Self {
+            acquire_timeout: Duration::from_secs(args.db_acquire_timeout),
+            connect_retries: args.db_connect_retries.max(1),
+            database_url,
+            idle_timeout: (args.db_idle_timeout > 0)
+                .then(|| Duration::from_secs(args.db_idle_timeout)),
+            max_connections: args.db_max_connections,
+            min_connections: args.db_min_connections,
+        })
+    }
+}
+
+// Settings read from the flags and the environment (or `.env`) once at startup.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub comment_max_depth: i32,
+    pub db: DbConfig,
+    // only needed to recognise password hashes created before per-user salts
+    pub hash_salt: Option<String>,
+    pub host: String,
+    pub jwt_secret: String,
+    pub max_page_size: i64,
+    pub migrate_on_start: bool,
+    pub page_size: i64,
+    pub port: u16,
+    pub shutdown_timeout: Duration,
+}
+
+impl Config {
+    pub fn load(args: Args) -> anyhow::Result<Self> {
+        // every missing variable is reported at once
+        let jwt_secret = required(&["DATABASE_URL", "JWT_SECRET"])?.remove(1);
+        let db = DbConfig::load(&args)?;
+
+        if args.page_size == 0 || args.page_size > args.max_page_size {
+            bail!(
+                "PAGE_SIZE must be positive and at most MAX_PAGE_SIZE ({} > {}).",
+                args.page_size,
+                args.max_page_size
+            );
+        }
+
+        if args.comment_max_depth == 0 || args.comment_max_depth > i32::MAX as u32 {
+            bail!(
+                "COMMENT_MAX_DEPTH must be between 1 and {} ({}).",
+                i32::MAX,
+                args.comment_max_depth
+            );
+        }
+
+        let hash_salt = std::env::var("HASH_SALT")
+            .ok()
+            .filter(|salt| !salt.is_empty());
+
+        Ok(Self {
+            comment_max_depth: args.comment_max_depth as i32,
+            db,
+            hash_salt,
+            host: args.host,
+            jwt_secret,
+            max_page_size: args.max_page_size as i64,
+            migrate_on_start: args.migrate_on_start,
+            page_size: args.page_size as i64,
+            port: args.port,
+            shutdown_timeout: Duration::from_secs(args.shutdown_timeout),
+        })
+    }
+}
+
+fn required(keys: &[&'static str]) -> anyhow::Result<Vec<String>> {
+    let mut missing = vec![];
+    let values = keys
+        .iter()
+        .map(|key| match std::env::var(key) {
+            Ok(value) if !value.trim().is_empty() => value,
+            _ => {
+                missing.push(*key);
+                String::new()
+            }
+        })
+        .collect();
+
+    if !missing.is_empty() {
+        bail!(
+            "Missing required environment variables: {}.",
+            missing.join(", ")
+        );
+    }
+
+    Ok(values)
+}
@@ -1,23 +1,59 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::{FromRow, PgPool, Pool, Postgres};
+use sqlx::{postgres::PgPoolOptions, FromRow, Pool, Postgres};
+use tracing::warn;
 use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::utils::{date_fmt, validate},
+    config::DbConfig,
+};
 
-use crate::api::utils::date_fmt;
+// Retries with exponential backoff so the app can start before the database is reachable.
+pub async fn establish_connection(config: &DbConfig) -> anyhow::Result<Pool<Postgres>> {
+    let options = PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(config.acquire_timeout)
+        .idle_timeout(config.idle_timeout);
 
-pub async fn establish_connection() -> Pool<Postgres> {
-    let db_url = std::env::var("DATABASE_URL").expect("`DATABASE_URL` must be set.");
-    let pool = PgPool::connect(&db_url)
-        .await
-        .expect("Failed to connect to database.");
-    pool
+    let mut backoff = Duration::from_secs(1);
+    let mut attempt = 1;
+    loop {
+        match options.clone().connect(&config.database_url).await {
+            Ok(pool) => return Ok(pool),
+            Err(err) if attempt < config.connect_retries => {
+                warn!(
+                    "Database connection attempt {}/{} failed: {}, retrying in {:?}",
+                    attempt, config.connect_retries, err, backoff
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_secs(30));
+                attempt += 1;
+            }
+            Err(err) => return Err(anyhow!(err).context("Failed to connect to database")),
+        }
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct NewUser {
+    #[validate(
+        email(message = "must be a valid email address"),
+        length(max = 254, message = "must be at most 254 characters")
+    )]
     pub email: String,
+    #[validate(custom(function = "validate::password"))]
     pub password: String,
+    #[validate(
+        length(min = 3, max = 32, message = "must be 3 to 32 characters"),
+        custom(function = "validate::username")
+    )]
     pub username: String,
 }
 
@@ -38,68 +74,119 @@ pub struct User {
     #[sqlx(default)]
     pub password: Option<String>,
     pub phone: String,
+    #[sqlx(default)]
+    pub timezone: Option<String>,
     #[serde(with = "date_fmt")]
     pub update_at: DateTime<Local>,
     pub username: String,
 }
 
-#[derive(Deserialize)]
-pub struct UserPayload {
+#[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
+pub struct Profile {
     pub _id: Uuid,
     pub avatar: String,
     pub bio: String,
+    pub following: bool,
+    pub nickname: String,
+    pub username: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UserPayload {
+    #[validate(length(max = 2048, message = "must be at most 2048 characters"))]
+    pub avatar: String,
+    #[validate(length(max = 1024, message = "must be at most 1024 characters"))]
+    pub bio: String,
+    #[validate(custom(function = "validate::birthday"))]
     pub birthday: String,
+    #[validate(
+        email(message = "must be a valid email address"),
+        length(max = 254, message = "must be at most 254 characters")
+    )]
     pub email: String,
+    #[validate(range(min = -1, max = 1, message = "must be -1, 0 or 1"))]
     pub gender: i16,
+    #[validate(length(max = 64, message = "must be at most 64 characters"))]
     pub job: String,
+    #[validate(length(max = 32, message = "must be at most 32 characters"))]
     pub nickname: String,
+    #[validate(custom(function = "validate::new_password"))]
     pub password: Option<String>,
+    #[validate(length(max = 32, message = "must be at most 32 characters"))]
     pub phone: String,
+    // An empty string clears the preference.
+    #[validate(custom(function = "validate::timezone"))]
+    pub timezone: Option<String>,
+    #[validate(
+        length(min = 3, max = 32, message = "must be 3 to 32 characters"),
+        custom(function = "validate::username")
+    )]
     pub username: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct NewTopic {
+    #[validate(length(min = 1, max = 65536, message = "must be 1 to 65536 characters"))]
     pub content: String,
+    #[validate(
+        length(max = 10, message = "must have at most 10 tags"),
+        custom(function = "validate::tags")
+    )]
     pub tags: Vec<String>,
+    #[validate(length(min = 1, max = 128, message = "must be 1 to 128 characters"))]
     pub title: String,
-    pub user_id: Uuid,
 }
 
 #[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
 pub struct Topic {
     pub _id: Uuid,
     pub comments: Vec<Uuid>,
-    #[sqlx(default)]
-    pub comments_arr: Option<Value>,
     pub content: String,
     #[sqlx(default)]
     pub content_clip: Option<String>,
+    // escaped search snippet with `<mark>` around the matches, only set on search results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub content_highlight: Option<String>,
+    #[sqlx(default)]
+    pub content_html: Option<String>,
     #[serde(with = "date_fmt")]
     pub create_at: DateTime<Local>,
     pub favorite: i32,
+    pub slug: String,
+    // keyset value of sorted listings, only there to build the next cursor
+    #[serde(skip)]
+    #[sqlx(default)]
+    pub sort_key: Option<i64>,
     // #[serde(bound = "T: PartialEq + Eq + PartialOrd + Ord")]
     pub tags: Vec<String>,
     pub title: String,
     #[sqlx(default)]
     pub title_clip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub title_highlight: Option<String>,
     #[serde(with = "date_fmt")]
     pub update_at: DateTime<Local>,
     #[sqlx(default)]
     pub update_at_str: Option<String>,
     pub user_id: Uuid,
     #[sqlx(default)]
-    pub user: Option<Value>
+    pub user: Option<Value>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct TopicPayload {
     pub _id: Uuid,
+    #[validate(length(min = 1, max = 65536, message = "must be 1 to 65536 characters"))]
     pub content: String,
+    #[validate(
+        length(max = 10, message = "must have at most 10 tags"),
+        custom(function = "validate::tags")
+    )]
     pub tags: Vec<String>,
-    pub tags_removed: Vec<String>,
+    #[validate(length(min = 1, max = 128, message = "must be 1 to 128 characters"))]
     pub title: String,
-    pub user_id: Uuid,
 }
 
 #[derive(Deserialize)]
@@ -112,24 +199,57 @@ pub struct FavorPayload {
 pub struct Comment {
     pub _id: Uuid,
     pub content: String,
+    #[sqlx(default)]
+    pub content_html: Option<String>,
     #[serde(with = "date_fmt")]
     pub create_at: DateTime<Local>,
+    pub parent_id: Option<Uuid>,
+    #[sqlx(skip)]
+    pub replies: Option<Vec<Comment>>,
+    #[sqlx(default)]
+    pub reply_count: Option<i64>,
     pub topic: Uuid,
+    #[serde(with = "date_fmt")]
+    pub update_at: DateTime<Local>,
     pub user_id: Uuid,
+    #[sqlx(default)]
+    pub user: Option<Value>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct NewComment {
+    #[validate(length(min = 1, max = 8192, message = "must be 1 to 8192 characters"))]
     pub content: String,
+    pub parent_id: Option<Uuid>,
     pub topic: Uuid,
-    pub user_id: Uuid,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CommentPayload {
+    #[validate(length(min = 1, max = 8192, message = "must be 1 to 8192 characters"))]
+    pub content: String,
 }
 
 #[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
 pub struct Tag {
     pub _id: Uuid,
+    pub count: i64,
     #[serde(with = "date_fmt")]
     pub create_at: DateTime<Local>,
     pub tag: String,
     pub topics: Vec<Uuid>,
 }
+
+#[derive(Clone, Debug, FromRow)]
+pub struct RefreshToken {
+    pub _id: Uuid,
+    pub expire_at: DateTime<Local>,
+    pub family: Uuid,
+    pub revoke_at: Option<DateTime<Local>>,
+    pub user_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct RefreshPayload {
+    pub refresh_token: String,
+}
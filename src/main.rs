@@ -1,20 +1,32 @@
+use std::future::IntoFuture;
+
 use axum::{
-    routing::{get, post},
+    routing::{get, post, put},
     Router,
 };
-use tokio::net::TcpListener;
+use clap::Parser;
+use tokio::{net::TcpListener, signal, sync::oneshot};
 use tower_http::trace::{DefaultMakeSpan, DefaultOnRequest, DefaultOnResponse, TraceLayer};
-use tracing::{info, Level};
+use tracing::{error, info, warn, Level};
 use tracing_subscriber::fmt::time::ChronoLocal;
 
 mod api;
+mod config;
 mod db;
+mod migrate;
+mod state;
 
-use self::api::{tag, topic, user};
+use self::{
+    api::{comment, health, search, tag, token, topic, user, v2},
+    config::{Args, Command, Config, DbConfig},
+    state::AppState,
+};
 
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().ok();
+    let mut args = Args::parse();
+    let command = args.command.take();
 
     tracing_subscriber::fmt()
         .with_timer(ChronoLocal::new("%Y-%m-%d %H:%M:%S".to_string()))
@@ -27,12 +39,61 @@ async fn main() {
         .on_request(DefaultOnRequest::new().level(Level::INFO))
         .on_response(DefaultOnResponse::new().level(Level::INFO));
 
-    let pool = db::establish_connection().await;
+    // Migrations only need the database, deploy jobs running them don't have the app secrets.
+    if let Some(Command::Migrate { action }) = command {
+        let pool = match DbConfig::load(&args) {
+            Ok(db_config) => db::establish_connection(&db_config).await,
+            Err(err) => Err(err),
+        };
+        let pool = match pool {
+            Ok(pool) => pool,
+            Err(err) => {
+                error!("{:#}", err);
+                std::process::exit(1);
+            }
+        };
+        let res = migrate::run(&pool, action).await;
+        pool.close().await;
+        if let Err(err) = res {
+            error!("Migration failed: {:#}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let config = match Config::load(args) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("{:#}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let pool = match db::establish_connection(&config.db).await {
+        Ok(pool) => pool,
+        Err(err) => {
+            error!("{:#}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if config.migrate_on_start {
+        if let Err(err) = migrate::up(&pool).await {
+            error!("Migration failed: {}", err);
+            std::process::exit(1);
+        }
+    }
+    let state = AppState::new(config, pool.clone());
+    let config = state.config.clone();
 
     let app = Router::new()
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
         .route("/api/", get(topic::get_topics))
         .route("/api/register", post(user::register))
         .route("/api/login", post(user::login))
+        .route("/api/logout", post(token::logout))
+        .route("/api/token/refresh", post(token::refresh))
         .route("/api/user/:username", get(user::get_user))
         .route("/api/user/list", get(user::get_users))
         .route("/api/settings", get(user::get_my_settings))
@@ -40,23 +101,114 @@ async fn main() {
         .route("/api/my-topics", get(user::get_my_topics))
         .route("/api/my-favorites", get(user::get_my_favorites))
         .route("/api/favor", post(user::favor))
+        .route(
+            "/api/topic/:topic_id/favorite",
+            post(user::favorite_topic).delete(user::unfavorite_topic),
+        )
+        .route("/api/feed", get(topic::get_feed))
+        .route("/api/search", get(search::search))
         .route("/api/topic/initiate", post(topic::create_topic))
-        .route("/api/topic/:topic_id", get(topic::get_topic))
+        .route(
+            "/api/topic/:topic_id",
+            get(topic::get_topic).delete(topic::delete_topic),
+        )
+        .route(
+            "/api/topic/:topic_id/comments",
+            get(topic::get_topic_comments),
+        )
         .route("/api/topic/update/:topic_id", get(topic::get_update_topic))
         .route("/api/topic/update", post(topic::topic_update))
         .route("/api/topic/comment", post(topic::topic_comment))
+        .route(
+            "/api/comment/:comment_id",
+            put(comment::update_comment).delete(comment::delete_comment),
+        )
         .route("/api/profile/:username", get(topic::get_user_profile))
+        .route(
+            "/api/profile/:username/follow",
+            post(user::follow).delete(user::unfollow),
+        )
         .route(
             "/api/profile/:username/favorites",
             get(topic::get_user_favorites),
         )
         .route("/api/tags", get(tag::get_tags))
         .route("/api/tags/:tag", get(tag::get_topics_by_tag))
-        .with_state(pool)
+        .nest("/api/v2", v2::router())
+        .with_state(state)
         .layer(trace_layer);
 
-    let addr = std::net::SocketAddr::from(([127, 0, 0, 1], 3001));
-    info!("Server listening on {}", addr);
-    let listener = TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    let listener = match TcpListener::bind((config.host.as_str(), config.port)).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Failed to bind {}:{}: {}", config.host, config.port, err);
+            std::process::exit(1);
+        }
+    };
+    info!("Server listening on {}", listener.local_addr().unwrap());
+
+    // The drain timeout only starts once a shutdown signal arrived.
+    let (signal_tx, signal_rx) = oneshot::channel();
+    let server = axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            let _ = signal_tx.send(());
+        })
+        .into_future();
+    let drain_deadline = async {
+        if signal_rx.await.is_ok() {
+            tokio::time::sleep(config.shutdown_timeout).await;
+        } else {
+            std::future::pending::<()>().await;
+        }
+    };
+
+    let drained = tokio::select! {
+        res = server => {
+            if let Err(err) = res {
+                error!("Server error: {}", err);
+            }
+            true
+        }
+        _ = drain_deadline => {
+            warn!(
+                "In-flight requests did not finish within {:?}, shutting down anyway",
+                config.shutdown_timeout
+            );
+            false
+        }
+    };
+
+    // Closing the pool waits for every checked out connection, the ones stuck requests still
+    // hold past the deadline are dropped on exit instead.
+    if drained {
+        pool.close().await;
+    }
+    info!("Server stopped");
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("Failed to install the Ctrl+C handler.");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to install the SIGTERM handler.")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutdown signal received, draining in-flight requests");
 }
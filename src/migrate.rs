@@ -0,0 +1,218 @@
+use anyhow::Context;
+use clap::Subcommand;
+use sqlx::{
+    migrate::{Migrate, MigrateError, Migrator},
+    Pool, Postgres, Transaction,
+};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::api::{common, utils::markdown};
+
+// Everything under `migrations/` is compiled into the binary.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+#[derive(Debug, Subcommand)]
+pub enum MigrateAction {
+    /// Apply every pending migration
+    Up,
+    /// Revert the most recently applied migrations
+    Down {
+        /// How many migrations to revert
+        #[arg(default_value_t = 1)]
+        steps: usize,
+    },
+    /// List the migrations and whether they are applied
+    Status,
+    /// Fill in derived data SQL migrations can't compute, safe to run again
+    Backfill,
+}
+
+pub async fn run(pool: &Pool<Postgres>, action: MigrateAction) -> anyhow::Result<()> {
+    match action {
+        MigrateAction::Up => up(pool).await?,
+        MigrateAction::Down { steps } => down(pool, steps).await?,
+        MigrateAction::Status => status(pool).await?,
+        MigrateAction::Backfill => backfill(pool).await?,
+    }
+
+    Ok(())
+}
+
+pub async fn up(pool: &Pool<Postgres>) -> Result<(), MigrateError> {
+    ensure_migrations_table(pool).await?;
+    let pending = pending(pool).await?;
+    MIGRATOR.run(pool).await?;
+    info!("Applied {} migration(s)", pending.len());
+
+    Ok(())
+}
+
+static BACKFILL_BATCH: i64 = 500;
+
+async fn backfill(pool: &Pool<Postgres>) -> anyhow::Result<()> {
+    reslug_topics(pool)
+        .await
+        .context("Backfilling topic slugs failed")?;
+    render_missing_html(pool, "topics")
+        .await
+        .context("Rendering topics failed")?;
+    render_missing_html(pool, "comments")
+        .await
+        .context("Rendering comments failed")?;
+
+    Ok(())
+}
+
+// Backfill transactions leave `update_at` alone, see the `update_at_column` trigger.
+async fn begin_backfill(pool: &Pool<Postgres>) -> anyhow::Result<Transaction<'static, Postgres>> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("set local conduit.backfill = 'on'")
+        .execute(&mut *tx)
+        .await?;
+
+    Ok(tx)
+}
+
+// The `topics_slug` migration derived slugs for existing topics in SQL, those get one from
+// `topic_slug::slugify` like every new topic. Their old slug keeps resolving as an alias.
+async fn reslug_topics(pool: &Pool<Postgres>) -> anyhow::Result<()> {
+    let mut after = Uuid::nil();
+    let mut reslugged = 0;
+    loop {
+        let mut tx = begin_backfill(pool).await?;
+        let rows: Vec<(Uuid, String, String)> = sqlx::query_as(
+            r#"
+                select _id, title, slug
+                from topics
+                where _id > $1
+                    and slug = coalesce(nullif(trim(both '-' from lower(regexp_replace(title, '[^[:alnum:]]+', '-', 'g'))), ''), 'topic') || '-' || left(_id::text, 8)
+                order by _id
+                limit $2
+                for update
+            "#,
+        )
+        .bind(after)
+        .bind(BACKFILL_BATCH)
+        .fetch_all(&mut *tx)
+        .await?;
+        let Some((last, _, _)) = rows.last() else {
+            break;
+        };
+        after = *last;
+
+        reslugged += rows.len();
+        for (topic_id, title, slug) in rows {
+            common::replace_topic_slug(&mut tx, topic_id, &title, slug).await?;
+        }
+        tx.commit().await?;
+    }
+
+    info!("Regenerated the slugs of {} topic(s)", reslugged);
+
+    Ok(())
+}
+
+// `content_html` is rendered in Rust so SQL migrations can't fill it in, rows written before
+// the column existed are rendered here in batches.
+async fn render_missing_html(pool: &Pool<Postgres>, table: &str) -> anyhow::Result<()> {
+    let mut rendered = 0;
+    loop {
+        let mut tx = begin_backfill(pool).await?;
+        let rows: Vec<(Uuid, String)> = sqlx::query_as(&format!(
+            "select _id, content from {} where content_html is null limit $1 for update",
+            table
+        ))
+        .bind(BACKFILL_BATCH)
+        .fetch_all(&mut *tx)
+        .await?;
+        if rows.is_empty() {
+            break;
+        }
+
+        let (ids, html): (Vec<Uuid>, Vec<String>) = rows
+            .iter()
+            .map(|(id, content)| (*id, markdown::render(content)))
+            .unzip();
+
+        sqlx::query(&format!(
+            r#"
+                update {} t
+                set content_html = r.html
+                from unnest($1::uuid[], $2::text[]) as r(_id, html)
+                where t._id = r._id
+            "#,
+            table
+        ))
+        .bind(&ids)
+        .bind(&html)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        rendered += rows.len();
+    }
+
+    if rendered > 0 {
+        info!("Rendered content_html for {} {}", rendered, table);
+    }
+
+    Ok(())
+}
+
+async fn down(pool: &Pool<Postgres>, steps: usize) -> Result<(), MigrateError> {
+    ensure_migrations_table(pool).await?;
+    let mut applied = applied(pool).await?;
+    applied.sort_unstable_by(|a, b| b.cmp(a));
+
+    // `undo` reverts everything newer than the target version.
+    let target = applied.get(steps).copied().unwrap_or(0);
+    MIGRATOR.undo(pool, target).await?;
+    info!("Reverted {} migration(s)", steps.min(applied.len()));
+
+    Ok(())
+}
+
+async fn status(pool: &Pool<Postgres>) -> Result<(), MigrateError> {
+    ensure_migrations_table(pool).await?;
+    let applied = applied(pool).await?;
+
+    for migration in MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+    {
+        let state = if applied.contains(&migration.version) {
+            "applied"
+        } else {
+            "pending"
+        };
+        println!(
+            "{} {:<8} {}",
+            migration.version, state, migration.description
+        );
+    }
+
+    Ok(())
+}
+
+async fn ensure_migrations_table(pool: &Pool<Postgres>) -> Result<(), MigrateError> {
+    pool.acquire().await?.ensure_migrations_table().await
+}
+
+async fn applied(pool: &Pool<Postgres>) -> Result<Vec<i64>, MigrateError> {
+    let applied = pool.acquire().await?.list_applied_migrations().await?;
+
+    Ok(applied.into_iter().map(|m| m.version).collect())
+}
+
+// Versions compiled into the binary that the database has not seen yet,
+// fails when the migrations table does not exist.
+pub async fn pending(pool: &Pool<Postgres>) -> Result<Vec<i64>, MigrateError> {
+    let applied = applied(pool).await?;
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && !applied.contains(&m.version))
+        .map(|m| m.version)
+        .collect())
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+use sqlx::{Pool, Postgres};
+
+use crate::{api::utils::jwt::Keys, config::Config};
+
+// Shared by every handler, handlers that only need the pool can keep extracting `State<Pool<Postgres>>`.
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub keys: Arc<Keys>,
+    pub pool: Pool<Postgres>,
+}
+
+impl AppState {
+    pub fn new(config: Config, pool: Pool<Postgres>) -> Self {
+        let keys = Keys::new(config.jwt_secret.as_bytes());
+
+        Self {
+            config: Arc::new(config),
+            keys: Arc::new(keys),
+            pool,
+        }
+    }
+}
+
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for Pool<Postgres> {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Keys> {
+    fn from_ref(state: &AppState) -> Self {
+        state.keys.clone()
+    }
+}